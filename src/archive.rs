@@ -0,0 +1,75 @@
+use flate2::read::GzDecoder;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A regular file read out of an archive.
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+/// Reads every regular file from a `.zip`, `.tar`, `.tar.gz` or `.tgz` archive.
+///
+/// Directory entries, links and other special entries are skipped.
+pub fn read_entries(path: &Path) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    if name.ends_with(".zip") {
+        read_zip(File::open(path)?)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        read_tar(GzDecoder::new(File::open(path)?))
+    } else if name.ends_with(".tar") {
+        read_tar(File::open(path)?)
+    } else {
+        Err(format!(
+            "unsupported archive {:?}: expected .zip, .tar, .tar.gz or .tgz",
+            path
+        )
+        .into())
+    }
+}
+
+fn read_zip(file: File) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut entries = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        // Entries with unsafe names (absolute or escaping via `..`) are skipped.
+        let Some(path) = entry.enclosed_name() else {
+            continue;
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push(ArchiveEntry { path, data });
+    }
+
+    Ok(entries)
+}
+
+fn read_tar<R: Read>(reader: R) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push(ArchiveEntry { path, data });
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,179 @@
+//! Dropping whole files until the buffer fits a size, for `--fit-clipboard`
+//! and `--interactive-budget`.
+//!
+//! Sizes are worked out per file with [`buffer_share`] or [`token_share`],
+//! so the title and summary blocks (and, without `--tokenizer`, the token
+//! estimate itself) come on top of the limit. `--interactive-budget` lets
+//! the user pick the files to drop on a terminal, and falls back to the
+//! largest-first order of `--fit-clipboard`.
+
+use crate::tokenizer::{self, count_tokens, describe_tokens};
+use crate::{estimate_tokens_from_bytes, format_size, log_noted_skip, CollectedFile, FitDrop};
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+
+/// The bytes `file` adds to the buffer: its content, its header and its tree
+/// line, roughly.
+pub fn buffer_share(file: &CollectedFile) -> u64 {
+    (file.content.len() + 2 * file.display_path.len() + 16) as u64
+}
+
+/// The tokens `file` adds to the buffer: with `--tokenizer`, its content
+/// counted exactly and the rest of [`buffer_share`] estimated; otherwise all
+/// of it estimated.
+pub fn token_share(file: &CollectedFile) -> u64 {
+    let bytes = buffer_share(file);
+    if !tokenizer::is_exact() {
+        return estimate_tokens_from_bytes(bytes);
+    }
+    count_tokens(&file.content) as u64
+        + estimate_tokens_from_bytes(bytes - file.content.len() as u64)
+}
+
+/// The indices of the files to drop, in `drop` order, for the rest to add up
+/// to at most `limit` by their `sizes`. Once enough is dropped, files dropped
+/// early that fit in the room left are kept after all.
+pub fn files_to_drop(
+    files: &[CollectedFile],
+    sizes: &[u64],
+    limit: u64,
+    drop: FitDrop,
+) -> HashSet<usize> {
+    let mut total: u64 = sizes.iter().sum();
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    match drop {
+        FitDrop::Largest => order.sort_by_key(|&i| std::cmp::Reverse(files[i].content.len())),
+        FitDrop::Smallest => order.sort_by_key(|&i| files[i].content.len()),
+        FitDrop::Last => order.reverse(),
+    }
+    let mut dropping = Vec::new();
+    for i in order {
+        if total <= limit {
+            break;
+        }
+        total -= sizes[i];
+        dropping.push(i);
+    }
+    let mut dropped = HashSet::new();
+    for i in dropping.into_iter().rev() {
+        if total + sizes[i] <= limit {
+            total += sizes[i];
+        } else {
+            dropped.insert(i);
+        }
+    }
+    dropped
+}
+
+/// Removes the files at the `dropped` indices, naming each with `flag` as
+/// the reason, and returns their bytes.
+pub fn drop_files(
+    files: &mut Vec<CollectedFile>,
+    dropped: &HashSet<usize>,
+    flag: &str,
+    raw_bytes: bool,
+) -> u64 {
+    let mut dropped_bytes = 0;
+    let mut i = 0;
+    files.retain(|file| {
+        i += 1;
+        if !dropped.contains(&(i - 1)) {
+            return true;
+        }
+        dropped_bytes += file.content.len() as u64;
+        log_noted_skip(
+            &file.relative_path,
+            &format!(
+                "dropped to fit {} ({})",
+                flag,
+                format_size(file.content.len(), raw_bytes)
+            ),
+        );
+        false
+    });
+    dropped_bytes
+}
+
+/// Files listed by `--interactive-budget`, largest first; the smaller ones
+/// are always kept.
+const PICKER_SHOWN: usize = 40;
+
+/// Lists the largest files on stderr and lets the user toggle them by number
+/// until the kept ones fit in `budget` by their `tokens`, then returns the
+/// indices of the dropped ones. `a`, or the end of stdin while still over budget,
+/// leaves the choice to [`files_to_drop`].
+pub fn pick_files_to_drop(
+    files: &[CollectedFile],
+    tokens: &[u64],
+    budget: u64,
+) -> io::Result<HashSet<usize>> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(tokens[i]));
+    order.truncate(PICKER_SHOWN);
+    let total: u64 = tokens.iter().sum();
+    let mut dropped = HashSet::new();
+    let mut stdin = io::stdin().lock();
+    loop {
+        let kept = total - dropped.iter().map(|&i| tokens[i]).sum::<u64>();
+        eprintln!(
+            "\nKeeping {} for a budget of {}, largest files first:",
+            describe_tokens(kept as usize),
+            budget
+        );
+        for (n, &i) in order.iter().enumerate() {
+            eprintln!(
+                "  {:>3}. [{}] {:>8} tokens  {}",
+                n + 1,
+                if dropped.contains(&i) { ' ' } else { 'x' },
+                tokens[i],
+                files[i].display_path
+            );
+        }
+        if files.len() > order.len() {
+            eprintln!(
+                "       ... and {} smaller files, kept",
+                files.len() - order.len()
+            );
+        }
+        if kept <= budget {
+            eprint!("Toggle files by number (e.g. 1 3-5), or press Enter to go on: ");
+        } else {
+            eprint!(
+                "Over by {}. Toggle files by number (e.g. 1 3-5), or `a` to drop the largest: ",
+                describe_tokens((kept - budget) as usize)
+            );
+        }
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            eprintln!();
+            if kept <= budget {
+                return Ok(dropped);
+            }
+            break;
+        }
+        match line.trim() {
+            "" if kept <= budget => return Ok(dropped),
+            "" => {}
+            "a" => break,
+            input => {
+                for word in input.split([' ', ',']).filter(|word| !word.is_empty()) {
+                    let range = match word.split_once('-') {
+                        Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+                        None => word.parse().ok().map(|n| (n, n)),
+                    };
+                    match range {
+                        Some((start, end)) if 1 <= start && start <= end && end <= order.len() => {
+                            for &i in &order[start - 1..end] {
+                                if !dropped.remove(&i) {
+                                    dropped.insert(i);
+                                }
+                            }
+                        }
+                        _ => eprintln!("Not a file number or range of them: {:?}", word),
+                    }
+                }
+            }
+        }
+    }
+    Ok(files_to_drop(files, tokens, budget, FitDrop::Largest))
+}
@@ -0,0 +1,91 @@
+//! An on-disk cache of file reads for `--cache`, so repeated runs over a
+//! large, mostly unchanged tree skip re-reading and re-decoding files.
+//!
+//! Each cached file gets one entry file, named after the SHA-256 of its
+//! canonical path. The entry's first line records the modification time and
+//! size it was read at; the rest is the content. An entry whose recorded
+//! time or size no longer matches the file is ignored and overwritten.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &str = "code_collector-cache-v1";
+
+/// What a cached read produced.
+pub enum CachedRead {
+    Text(String),
+    /// The file wasn't valid UTF-8.
+    Binary,
+}
+
+pub struct ReadCache {
+    dir: PathBuf,
+}
+
+impl ReadCache {
+    /// Opens (creating if needed) the cache in `dir`.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(ReadCache {
+            dir: dir.to_owned(),
+        })
+    }
+
+    /// Deletes every entry in `dir`.
+    pub fn clear(dir: &Path) -> io::Result<()> {
+        match fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+        let name: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.dir.join(name)
+    }
+
+    fn stamp(metadata: &Metadata) -> Option<String> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(format!("{} {}", modified.as_nanos(), metadata.len()))
+    }
+
+    /// The cached read of `path`, if there is one for its current mtime and size.
+    pub fn get(&self, path: &Path, metadata: &Metadata) -> Option<CachedRead> {
+        let stamp = Self::stamp(metadata)?;
+        let entry = fs::read(self.entry_path(path)).ok()?;
+        let newline = entry.iter().position(|&b| b == b'\n')?;
+        let header = std::str::from_utf8(&entry[..newline]).ok()?;
+
+        let (magic, rest) = header.split_once(' ')?;
+        let (entry_stamp, kind) = rest.rsplit_once(' ')?;
+        if magic != MAGIC || entry_stamp != stamp {
+            return None;
+        }
+        match kind {
+            "binary" => Some(CachedRead::Binary),
+            "text" => String::from_utf8(entry[newline + 1..].to_vec())
+                .ok()
+                .map(CachedRead::Text),
+            _ => None,
+        }
+    }
+
+    /// Records the read of `path`. Failures only cost a cache miss next time,
+    /// so they are ignored.
+    pub fn put(&self, path: &Path, metadata: &Metadata, read: &CachedRead) {
+        let Some(stamp) = Self::stamp(metadata) else {
+            return;
+        };
+        let (kind, content) = match read {
+            CachedRead::Text(content) => ("text", content.as_str()),
+            CachedRead::Binary => ("binary", ""),
+        };
+        let entry = format!("{} {} {}\n{}", MAGIC, stamp, kind, content);
+        let _ = fs::write(self.entry_path(path), entry);
+    }
+}
@@ -0,0 +1,126 @@
+//! Clipboard access with a choice of backend for `--clipboard-backend`.
+//!
+//! `arboard` picks the backend itself and, as built here (without its
+//! `wayland-data-control` feature), always speaks X11, which on a Wayland
+//! desktop only reaches XWayland clients. The `wayland` backend therefore
+//! hands the text to `wl-copy` from wl-clipboard instead. HTML always goes
+//! through `arboard`.
+
+use arboard::Clipboard;
+use clap::ValueEnum;
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Which clipboard to talk to on Linux.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum ClipboardBackend {
+    /// Let the clipboard library decide
+    #[default]
+    Auto,
+    /// The X11 clipboard, even when a Wayland display is set
+    X11,
+    /// The Wayland clipboard, through `wl-copy`
+    Wayland,
+}
+
+pub struct SystemClipboard {
+    /// Opened up front unless `wl-copy` is used, and lazily if that fails.
+    arboard: Option<Clipboard>,
+    wl_copy: bool,
+}
+
+impl SystemClipboard {
+    /// Opens the clipboard, falling back to `Auto` with a warning when the
+    /// requested backend isn't available.
+    pub fn open(backend: ClipboardBackend) -> Result<Self, Box<dyn Error>> {
+        if backend != ClipboardBackend::Auto && !cfg!(target_os = "linux") {
+            eprintln!("--clipboard-backend only applies on Linux; using the system clipboard");
+            return Self::open(ClipboardBackend::Auto);
+        }
+        match backend {
+            ClipboardBackend::Auto => {}
+            ClipboardBackend::X11 => {
+                // Keeps arboard from choosing Wayland when it is built to support it.
+                std::env::remove_var("WAYLAND_DISPLAY");
+            }
+            ClipboardBackend::Wayland if std::env::var_os("WAYLAND_DISPLAY").is_none() => {
+                eprintln!("WAYLAND_DISPLAY is not set; falling back to --clipboard-backend auto");
+            }
+            ClipboardBackend::Wayland => {
+                return Ok(SystemClipboard {
+                    arboard: None,
+                    wl_copy: true,
+                })
+            }
+        }
+        Ok(SystemClipboard {
+            arboard: Some(Clipboard::new()?),
+            wl_copy: false,
+        })
+    }
+
+    fn arboard(&mut self) -> Result<&mut Clipboard, Box<dyn Error>> {
+        if self.arboard.is_none() {
+            self.arboard = Some(Clipboard::new()?);
+        }
+        Ok(self.arboard.as_mut().expect("opened above"))
+    }
+
+    pub fn set_text(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        if self.wl_copy {
+            match wl_copy(text) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    eprintln!(
+                        "Could not copy with wl-copy ({}); falling back to --clipboard-backend auto",
+                        e
+                    );
+                    self.wl_copy = false;
+                }
+            }
+        }
+        Ok(self.arboard()?.set_text(text)?)
+    }
+
+    /// The text on the clipboard, read with `wl-paste` when `wl-copy` is used.
+    pub fn get_text(&mut self) -> Result<String, Box<dyn Error>> {
+        if self.wl_copy {
+            let output = Command::new("wl-paste")
+                .arg("--no-newline")
+                .output()
+                .map_err(|e| format!("could not run wl-paste: {}", e))?;
+            if !output.status.success() {
+                return Err(format!("wl-paste failed ({})", output.status).into());
+            }
+            return Ok(String::from_utf8(output.stdout)?);
+        }
+        Ok(self.arboard()?.get_text()?)
+    }
+
+    /// Sets HTML with `alt_text` as the plain-text version.
+    pub fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), Box<dyn Error>> {
+        if self.wl_copy {
+            eprintln!("--html needs the clipboard library; ignoring --clipboard-backend wayland");
+            self.wl_copy = false;
+        }
+        Ok(self.arboard()?.set_html(html, Some(alt_text))?)
+    }
+}
+
+fn wl_copy(text: &str) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("wl-copy")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run wl-copy: {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("wl-copy failed ({})", status).into());
+    }
+    Ok(())
+}
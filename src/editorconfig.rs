@@ -0,0 +1,170 @@
+//! A minimal `.editorconfig` reader for `--respect-editorconfig`.
+//!
+//! Only `root`, section globs, `insert_final_newline` and `charset` are
+//! understood. Files are looked up from a file's directory upwards, stopping
+//! at one with `root = true`; closer files and later sections win, as in the
+//! EditorConfig spec. Numeric ranges (`{1..3}`) in globs aren't supported.
+
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The properties that apply to one file; `None` where no section sets them.
+#[derive(Default)]
+pub struct Properties {
+    pub insert_final_newline: Option<bool>,
+    pub charset: Option<String>,
+}
+
+struct Section {
+    /// `None` for a glob that couldn't be compiled; such a section matches nothing.
+    matcher: Option<GlobMatcher>,
+    insert_final_newline: Option<bool>,
+    charset: Option<String>,
+}
+
+struct ConfigFile {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+/// Parsed `.editorconfig` files, cached by directory.
+#[derive(Default)]
+pub struct EditorConfigs {
+    files: HashMap<PathBuf, Option<ConfigFile>>,
+}
+
+impl EditorConfigs {
+    /// The properties the `.editorconfig` files above `path` give it.
+    pub fn properties(&mut self, path: &Path) -> Properties {
+        let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let mut dirs = Vec::new();
+        for dir in absolute.ancestors().skip(1) {
+            let file = self
+                .files
+                .entry(dir.to_owned())
+                .or_insert_with(|| parse_file(dir));
+            let root = file.as_ref().is_some_and(|file| file.root);
+            dirs.push(dir.to_owned());
+            if root {
+                break;
+            }
+        }
+
+        // Apply the farthest file first so closer ones override it.
+        let mut properties = Properties::default();
+        for dir in dirs.iter().rev() {
+            let Some(file) = &self.files[dir] else {
+                continue;
+            };
+            let relative = absolute.strip_prefix(dir).unwrap_or(&absolute);
+            for section in file
+                .sections
+                .iter()
+                .filter(|s| s.matcher.as_ref().is_some_and(|m| m.is_match(relative)))
+            {
+                if section.insert_final_newline.is_some() {
+                    properties.insert_final_newline = section.insert_final_newline;
+                }
+                if section.charset.is_some() {
+                    properties.charset.clone_from(&section.charset);
+                }
+            }
+        }
+        properties
+    }
+}
+
+fn parse_file(dir: &Path) -> Option<ConfigFile> {
+    let text = fs::read_to_string(dir.join(".editorconfig")).ok()?;
+    let mut file = ConfigFile {
+        root: false,
+        sections: Vec::new(),
+    };
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let matcher = section_matcher(pattern);
+            if matcher.is_none() {
+                eprintln!(
+                    "Ignoring unsupported section [{}] in {:?}",
+                    pattern,
+                    dir.join(".editorconfig")
+                );
+            }
+            file.sections.push(Section {
+                matcher,
+                insert_final_newline: None,
+                charset: None,
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match file.sections.last_mut() {
+            None if key == "root" => file.root = value == "true",
+            None => {}
+            Some(section) => match key.as_str() {
+                "insert_final_newline" => {
+                    section.insert_final_newline = match value.as_str() {
+                        "true" => Some(true),
+                        "false" => Some(false),
+                        _ => None,
+                    }
+                }
+                "charset" => section.charset = (value != "unset").then_some(value),
+                _ => {}
+            },
+        }
+    }
+    Some(file)
+}
+
+/// A glob without a `/` matches the file name in any directory; one with a
+/// `/` is anchored to the `.editorconfig`'s directory.
+fn section_matcher(pattern: &str) -> Option<GlobMatcher> {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .ok()
+        .map(|glob| glob.compile_matcher())
+}
+
+/// Why `content` (read as UTF-8, BOM still in place) doesn't look like
+/// `charset`, if it doesn't.
+pub fn charset_mismatch(charset: &str, content: &str) -> Option<&'static str> {
+    let has_bom = content.starts_with('\u{feff}');
+    match charset {
+        "utf-8" if has_bom => Some("has a byte order mark, but charset is utf-8"),
+        "utf-8-bom" if !has_bom => Some("has no byte order mark, but charset is utf-8-bom"),
+        "latin1" if !content.is_ascii() => {
+            Some("is UTF-8 with non-ASCII text, but charset is latin1")
+        }
+        "utf-16be" | "utf-16le" => Some("is UTF-8, but charset is UTF-16"),
+        _ => None,
+    }
+}
+
+/// Adds a missing final newline, or removes trailing ones, as `insert` says.
+pub fn apply_final_newline(mut content: String, insert: bool) -> String {
+    if insert {
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+    } else {
+        let trimmed = content.trim_end_matches(['\n', '\r']).len();
+        content.truncate(trimmed);
+    }
+    content
+}
@@ -0,0 +1,124 @@
+//! Shell-style expansion of `~` and environment variables in path arguments,
+//! for when the shell didn't do it (e.g. quoted arguments or config values).
+
+use std::env;
+
+/// Expands a leading `~` or `~user`, then `$VAR` and `${VAR}` references.
+///
+/// Used as a clap value parser, so errors are reported like any invalid argument.
+pub fn expand_path(input: &str) -> Result<String, String> {
+    expand_vars(&expand_tilde(input)?)
+}
+
+fn home_dir() -> Option<String> {
+    env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .ok()
+        .filter(|home| !home.is_empty())
+}
+
+fn expand_tilde(input: &str) -> Result<String, String> {
+    let Some(rest) = input.strip_prefix('~') else {
+        return Ok(input.to_string());
+    };
+    let (user, remainder) = match rest.find(['/', '\\']) {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+
+    let home = if user.is_empty() {
+        home_dir().ok_or("cannot expand `~`: no home directory is set")?
+    } else {
+        user_home_dir(user).ok_or_else(|| format!("cannot expand `~{}`: unknown user", user))?
+    };
+    Ok(format!("{}{}", home, remainder))
+}
+
+/// Looks up another user's home directory in the password database.
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<String> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| fields[5].to_string())
+    })
+}
+
+#[cfg(not(unix))]
+fn user_home_dir(_user: &str) -> Option<String> {
+    None
+}
+
+fn expand_vars(input: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = input;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        let (name, remainder) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("unterminated `${{` in {:?}", input))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        if name.is_empty() {
+            // A lone `$` isn't a variable reference; keep it.
+            out.push('$');
+        } else {
+            let value = env::var(name)
+                .map_err(|_| format!("environment variable `{}` is not set", name))?;
+            out.push_str(&value);
+        }
+        rest = remainder;
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilde_is_the_home_directory() {
+        let home = home_dir().expect("tests need a home directory");
+        assert_eq!(expand_path("~").unwrap(), home);
+        assert_eq!(
+            expand_path("~/projects/foo").unwrap(),
+            format!("{}/projects/foo", home)
+        );
+        assert_eq!(expand_path("a/~").unwrap(), "a/~");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tilde_user_is_their_home_directory() {
+        assert_eq!(expand_path("~root/src").unwrap(), "/root/src");
+        assert!(expand_path("~no-such-user-here/src").is_err());
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        env::set_var("CODE_COLLECTOR_TEST_EXPAND", "/data");
+        assert_eq!(
+            expand_path("$CODE_COLLECTOR_TEST_EXPAND/foo").unwrap(),
+            "/data/foo"
+        );
+        assert_eq!(
+            expand_path("${CODE_COLLECTOR_TEST_EXPAND}foo").unwrap(),
+            "/datafoo"
+        );
+        assert_eq!(expand_path("cost$").unwrap(), "cost$");
+        assert!(expand_path("$CODE_COLLECTOR_TEST_UNSET/foo").is_err());
+        assert!(expand_path("${CODE_COLLECTOR_TEST_EXPAND").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Finding what kept the walker away from a path, for `--explain`.
+//!
+//! The walker never yields the paths its ignore rules and filters prune, so
+//! they are found by walking again without any and comparing. Which ignore
+//! rule matched is looked up in the ignore files between the path and the
+//! collected directory; rules from above it, `.git/info/exclude` and the
+//! global gitignore aren't consulted.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The topmost paths under `directory` that the filtered walk skipped, with
+/// whether each is a directory. Their contents aren't listed.
+pub fn pruned_paths(
+    directory: &str,
+    walked: &HashSet<PathBuf>,
+    follow_links: bool,
+    max_open: usize,
+    max_depth: Option<usize>,
+) -> Vec<(PathBuf, bool)> {
+    let mut pruned = Vec::new();
+    let unfiltered = WalkDir::new(directory)
+        .follow_links(follow_links)
+        .max_open(max_open)
+        .max_depth(max_depth.unwrap_or(usize::MAX))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            if walked.contains(entry.path()) {
+                true
+            } else {
+                pruned.push((entry.path().to_owned(), entry.file_type().is_dir()));
+                false
+            }
+        });
+    // Errors here were already surfaced by the filtered walk.
+    for _ in unfiltered {}
+    pruned
+}
+
+/// The ignore files of each directory, read once as they're needed.
+pub struct IgnoreRules {
+    root: PathBuf,
+    /// Highest precedence first, as the walker applies them.
+    file_names: Vec<String>,
+    case_insensitive: bool,
+    loaded: HashMap<PathBuf, Vec<(String, Gitignore)>>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, custom_file_names: &[&str], case_insensitive: bool) -> Self {
+        let mut file_names: Vec<String> = custom_file_names.iter().map(|s| s.to_string()).collect();
+        file_names.extend([".ignore".to_string(), ".gitignore".to_string()]);
+        IgnoreRules {
+            root: root.to_owned(),
+            file_names,
+            case_insensitive,
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// The rule that ignores `path` and the file it's from, e.g.
+    /// `` `target/` in .gitignore ``, searching the nearest directory first.
+    pub fn matching_rule(&mut self, path: &Path, is_dir: bool) -> Option<String> {
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let (root, file_names, case_insensitive) =
+                (&self.root, &self.file_names, self.case_insensitive);
+            let ignores = self.loaded.entry(dir.to_owned()).or_insert_with(|| {
+                file_names
+                    .iter()
+                    .filter(|name| dir.join(name).is_file())
+                    .filter_map(|name| {
+                        let mut builder = GitignoreBuilder::new(dir);
+                        builder.case_insensitive(case_insensitive).ok()?;
+                        builder.add(dir.join(name));
+                        let shown = dir.join(name);
+                        let shown = shown.strip_prefix(root).unwrap_or(&shown);
+                        Some((shown.display().to_string(), builder.build().ok()?))
+                    })
+                    .collect()
+            });
+            for (file, ignore) in ignores.iter() {
+                let matched = ignore.matched(path, is_dir);
+                if matched.is_whitelist() {
+                    return None;
+                }
+                if let Some(glob) = matched.inner().filter(|_| matched.is_ignore()) {
+                    return Some(format!("`{}` in {}", glob.original(), file));
+                }
+            }
+        }
+        None
+    }
+}
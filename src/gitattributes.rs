@@ -0,0 +1,148 @@
+//! A minimal `.gitattributes` reader for the linguist attributes that mark
+//! files as generated or vendored.
+//!
+//! Only the pattern syntax is interpreted (anchored vs. basename patterns,
+//! `*`, `**`, `?` and character classes); macros and `[attr]` definitions
+//! are ignored.
+
+use globset::{GlobBuilder, GlobMatcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ATTRIBUTES: &[&str] = &["linguist-generated", "linguist-vendored"];
+
+struct Rule {
+    /// Directory of the `.gitattributes` file, relative to the collected root.
+    dir: PathBuf,
+    matcher: GlobMatcher,
+    /// Patterns without a slash match a file name at any depth.
+    basename_only: bool,
+    /// What the rule does to each of [`ATTRIBUTES`], in order: `Some(true)`
+    /// sets it, `Some(Some(false))` unsets it (`-attr` or `attr=false`) and
+    /// `Some(None)` returns it to unspecified (`!attr`).
+    states: [Option<Option<bool>>; 2],
+}
+
+#[derive(Default)]
+pub struct GitAttributes {
+    rules: Vec<Rule>,
+}
+
+impl GitAttributes {
+    /// Reads the `.gitattributes` file of each directory in `dirs` (relative to `root`).
+    ///
+    /// Directories should be given shallowest first, so deeper files override
+    /// shallower ones as in git.
+    pub fn load<'a>(root: &Path, dirs: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut attributes = GitAttributes::default();
+        for dir in dirs {
+            if let Ok(text) = fs::read_to_string(root.join(dir).join(".gitattributes")) {
+                attributes.parse(dir, &text);
+            }
+        }
+        attributes
+    }
+
+    fn parse(&mut self, dir: &Path, text: &str) {
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            if pattern.starts_with('#') || pattern.starts_with("[attr]") {
+                continue;
+            }
+
+            let mut states = [None; 2];
+            for field in fields {
+                let (state, field) = if let Some(field) = field.strip_prefix('-') {
+                    (Some(false), field)
+                } else if let Some(field) = field.strip_prefix('!') {
+                    (None, field)
+                } else {
+                    let (field, value) = field.split_once('=').unwrap_or((field, "true"));
+                    (Some(value != "false"), field)
+                };
+                if let Some(i) = ATTRIBUTES.iter().position(|&name| name == field) {
+                    states[i] = Some(state);
+                }
+            }
+            if states.iter().all(Option::is_none) {
+                continue;
+            }
+
+            let basename_only = !pattern.trim_end_matches('/').contains('/');
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build();
+            if let Ok(glob) = glob {
+                self.rules.push(Rule {
+                    dir: dir.to_owned(),
+                    matcher: glob.compile_matcher(),
+                    basename_only,
+                    states,
+                });
+            }
+        }
+    }
+
+    /// Whether `path` (relative to the collected root) is marked as generated or vendored.
+    pub fn is_generated_or_vendored(&self, path: &Path) -> bool {
+        let mut states = [None; 2];
+        for rule in &self.rules {
+            let Ok(local) = path.strip_prefix(&rule.dir) else {
+                continue;
+            };
+            let matched = if rule.basename_only {
+                local
+                    .file_name()
+                    .is_some_and(|name| rule.matcher.is_match(name))
+            } else {
+                rule.matcher.is_match(local)
+            };
+            if matched {
+                for (state, rule_state) in states.iter_mut().zip(rule.states) {
+                    if let Some(rule_state) = rule_state {
+                        *state = rule_state;
+                    }
+                }
+            }
+        }
+        states.contains(&Some(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(text: &str) -> GitAttributes {
+        let mut attributes = GitAttributes::default();
+        attributes.parse(Path::new(""), text);
+        attributes
+    }
+
+    #[test]
+    fn unspecified_clears_an_earlier_match() {
+        let attributes =
+            attributes("*.pb.go linguist-generated\napi/*.pb.go !linguist-generated\n");
+        assert!(attributes.is_generated_or_vendored(Path::new("x.pb.go")));
+        assert!(!attributes.is_generated_or_vendored(Path::new("api/x.pb.go")));
+    }
+
+    #[test]
+    fn unspecified_leaves_other_attributes_alone() {
+        let attributes = attributes("vendor/** linguist-vendored\nvendor/** !linguist-generated\n");
+        assert!(attributes.is_generated_or_vendored(Path::new("vendor/lib.js")));
+    }
+
+    #[test]
+    fn unset_and_false_override_set() {
+        let attributes = attributes(
+            "*.js linguist-generated\na.js -linguist-generated\nb.js linguist-generated=false\n",
+        );
+        assert!(attributes.is_generated_or_vendored(Path::new("c.js")));
+        assert!(!attributes.is_generated_or_vendored(Path::new("a.js")));
+        assert!(!attributes.is_generated_or_vendored(Path::new("b.js")));
+    }
+}
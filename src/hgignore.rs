@@ -0,0 +1,104 @@
+//! Mercurial's `.hgignore`, for `--respect-hgignore`.
+//!
+//! Only the file at the collected directory's root is read, as Mercurial
+//! only reads the one at the repository root. Patterns are regular
+//! expressions unless a `syntax: glob` line (or a `glob:` prefix) says
+//! otherwise; `include:` and `subinclude:` lines aren't followed.
+
+use crate::log::notice;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone)]
+pub struct HgIgnore {
+    globs: GlobSet,
+    regexes: Vec<Regex>,
+}
+
+#[derive(Clone, Copy)]
+enum Syntax {
+    Regexp,
+    /// Matches in any directory.
+    Glob,
+    /// Matches from the root only.
+    RootGlob,
+}
+
+impl HgIgnore {
+    /// Reads `root/.hgignore`, or returns `None` if there is none.
+    pub fn load(root: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(root.join(".hgignore")) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::parse(&text)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!(".hgignore: {}", e)))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut syntax = Syntax::Regexp;
+        let mut globs = GlobSetBuilder::new();
+        let mut regexes = Vec::new();
+        for line in text.lines() {
+            let line = line
+                .split_once('#')
+                .map_or(line, |(before, _)| before)
+                .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("syntax:") {
+                syntax = match name.trim() {
+                    "regexp" | "re" => Syntax::Regexp,
+                    "glob" | "relglob" => Syntax::Glob,
+                    "rootglob" => Syntax::RootGlob,
+                    other => return Err(format!("unknown syntax {:?}", other)),
+                };
+                continue;
+            }
+            let (syntax, pattern) = match line.split_once(':') {
+                Some(("re" | "regexp", pattern)) => (Syntax::Regexp, pattern),
+                Some(("glob" | "relglob", pattern)) => (Syntax::Glob, pattern),
+                Some(("rootglob", pattern)) => (Syntax::RootGlob, pattern),
+                Some(("include" | "subinclude", _)) => {
+                    notice!(".hgignore: not following {:?}", line);
+                    continue;
+                }
+                _ => (syntax, line),
+            };
+            match syntax {
+                Syntax::Regexp => {
+                    regexes.push(Regex::new(pattern).map_err(|e| e.to_string())?);
+                }
+                Syntax::Glob | Syntax::RootGlob => {
+                    let pattern = match syntax {
+                        Syntax::Glob => format!("**/{}", pattern),
+                        _ => pattern.to_string(),
+                    };
+                    globs.add(
+                        GlobBuilder::new(&pattern)
+                            .literal_separator(true)
+                            .build()
+                            .map_err(|e| e.to_string())?,
+                    );
+                }
+            }
+        }
+        Ok(HgIgnore {
+            globs: globs.build().map_err(|e| e.to_string())?,
+            regexes,
+        })
+    }
+
+    /// Whether a path, relative to the root, is ignored. Directories are
+    /// checked as the walk reaches them, so a match on one covers its contents.
+    pub fn is_ignored(&self, relative_path: &Path) -> bool {
+        let path = relative_path.to_string_lossy().replace('\\', "/");
+        self.globs.is_match(&path) || self.regexes.iter().any(|regex| regex.is_match(&path))
+    }
+}
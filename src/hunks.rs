@@ -0,0 +1,245 @@
+//! Changed-line ranges from `git diff` hunks, and the function spans around
+//! them, for `--changed-hunks`.
+//!
+//! Spans are found by scanning, not parsing: braces for Rust and indentation
+//! for Python. Braces inside strings and comments are skipped on a best-effort
+//! basis, so unusual literals (e.g. raw strings holding `}`) can throw the
+//! scan off; [`enclosing_spans`] then gives up rather than guess.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// An inclusive range of 1-based line numbers.
+pub type Span = (usize, usize);
+
+/// The changed line ranges in the new version of each file of a diff made
+/// with `-U0 --no-prefix`. A pure deletion is recorded as the line it
+/// happened after, so the function it was in still counts as changed.
+pub fn changed_lines(diff: &str) -> HashMap<PathBuf, Vec<Span>> {
+    let mut changed: HashMap<PathBuf, Vec<Span>> = HashMap::new();
+    let mut current: Option<PathBuf> = None;
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            current = (path != "/dev/null").then(|| PathBuf::from(path));
+        } else if let (Some(hunk), Some(path)) = (line.strip_prefix("@@ "), &current) {
+            // `@@ -a,b +c,d @@`, where a missing `,d` means one line.
+            let Some(new) = hunk.split_whitespace().find_map(|s| s.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, count) = match new.split_once(',') {
+                Some((start, count)) => (start.parse::<usize>(), count.parse::<usize>()),
+                None => (new.parse(), Ok(1)),
+            };
+            let (Ok(start), Ok(count)) = (start, count) else {
+                continue;
+            };
+            let span: Span = if count == 0 {
+                (start.max(1), start.max(1))
+            } else {
+                (start, start + count - 1)
+            };
+            changed.entry(path.clone()).or_default().push(span);
+        }
+    }
+    changed
+}
+
+/// The spans of the functions (or other blocks) of `content` that contain
+/// the `changed` lines, merged where they overlap. Changed lines outside any
+/// block are kept as they are.
+///
+/// Returns `None` if there is no scanner for `extension` or the scan failed.
+pub fn enclosing_spans(content: &str, extension: &str, changed: &[Span]) -> Option<Vec<Span>> {
+    let lines: Vec<&str> = content.lines().collect();
+    let blocks = match extension {
+        "rs" => brace_blocks(&lines)?,
+        "py" | "pyi" => indent_blocks(&lines),
+        _ => return None,
+    };
+
+    let mut spans: Vec<Span> = Vec::new();
+    for &(start, end) in changed {
+        let end = end.min(lines.len());
+        if start > end {
+            continue;
+        }
+        // The innermost block holding the whole change, or the change itself.
+        let span = blocks
+            .iter()
+            .filter(|block| block.0 <= start && end <= block.1)
+            .min_by_key(|block| block.1 - block.0)
+            .copied()
+            .unwrap_or((start, end));
+        spans.push(span);
+    }
+    spans.sort();
+
+    let mut merged: Vec<Span> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.0 <= last.1 + 1 => last.1 = last.1.max(span.1),
+            _ => merged.push(span),
+        }
+    }
+    Some(merged)
+}
+
+/// Whether a trimmed Rust line starts an item worth showing whole.
+fn starts_rust_item(line: &str) -> bool {
+    let mut words = line.split_whitespace().peekable();
+    while let Some(word) = words.peek() {
+        let is_modifier = word.starts_with("pub")
+            || matches!(*word, "async" | "const" | "unsafe" | "default")
+            || *word == "extern"
+            || word.starts_with('"');
+        if !is_modifier {
+            break;
+        }
+        words.next();
+    }
+    matches!(
+        words.next(),
+        Some("fn" | "impl" | "struct" | "enum" | "trait" | "mod" | "union" | "macro_rules!")
+    )
+}
+
+/// The spans of Rust items with a `{ ... }` body, each starting at its doc
+/// comments and attributes. `None` if the braces don't balance.
+fn brace_blocks(lines: &[&str]) -> Option<Vec<Span>> {
+    let depths = brace_depth_changes(lines)?;
+    let mut blocks = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !starts_rust_item(line.trim_start()) {
+            continue;
+        }
+        // Find where the body opened on this (or a later) line closes.
+        let mut depth = 0i64;
+        let mut opened = false;
+        let mut end = None;
+        for (j, &(opens, closes, ends_item)) in depths.iter().enumerate().skip(i) {
+            if !opened && opens == 0 && ends_item {
+                // A declaration without a body, e.g. `mod tree;`.
+                end = Some(j);
+                break;
+            }
+            depth += opens as i64;
+            opened |= opens > 0;
+            depth -= closes as i64;
+            if opened && depth <= 0 {
+                end = Some(j);
+                break;
+            }
+        }
+        let end = end?;
+        let mut start = i;
+        while start > 0 {
+            let above = lines[start - 1].trim_start();
+            if above.starts_with("///") || above.starts_with("#[") || above.starts_with("//!") {
+                start -= 1;
+            } else {
+                break;
+            }
+        }
+        blocks.push((start + 1, end + 1));
+    }
+    Some(blocks)
+}
+
+/// Per line: the `{` and `}` outside strings, chars and comments (which may
+/// span lines), and whether a `;` ends the line. `None` if the braces don't
+/// balance overall.
+fn brace_depth_changes(lines: &[&str]) -> Option<Vec<(usize, usize, bool)>> {
+    let mut in_block_comment = false;
+    let mut in_string = false;
+    let mut balance = 0i64;
+    let mut changes = Vec::with_capacity(lines.len());
+    for line in lines {
+        let (mut opens, mut closes) = (0, 0);
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_block_comment {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    in_block_comment = false;
+                }
+            } else if in_string {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match c {
+                    '/' if chars.peek() == Some(&'/') => break,
+                    '/' if chars.peek() == Some(&'*') => {
+                        chars.next();
+                        in_block_comment = true;
+                    }
+                    '"' => in_string = true,
+                    // A char literal like '{' (but not a lifetime like 'a).
+                    '\'' => {
+                        let rest: String = chars.clone().take(2).collect();
+                        if rest.starts_with('\\') {
+                            // Skip the escaped character, then up to the closing quote,
+                            // which covers '\'' and '\u{7b}' alike.
+                            chars.nth(1);
+                            for c in chars.by_ref() {
+                                if c == '\'' {
+                                    break;
+                                }
+                            }
+                        } else if rest.chars().nth(1) == Some('\'') {
+                            chars.nth(1);
+                        }
+                    }
+                    '{' => opens += 1,
+                    '}' => closes += 1,
+                    _ => {}
+                }
+            }
+        }
+        balance += opens as i64 - closes as i64;
+        if balance < 0 {
+            return None;
+        }
+        changes.push((opens, closes, line.trim_end().ends_with(';')));
+    }
+    (balance == 0).then_some(changes)
+}
+
+/// The spans of Python `def` and `class` blocks, each starting at its
+/// decorators and ending at its last non-blank line.
+fn indent_blocks(lines: &[&str]) -> Vec<Span> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut blocks = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let is_header = ["def ", "async def ", "class "]
+            .iter()
+            .any(|keyword| trimmed.starts_with(keyword));
+        if !is_header {
+            continue;
+        }
+        let level = indent(line);
+        let mut end = i;
+        for (j, below) in lines.iter().enumerate().skip(i + 1) {
+            if below.trim().is_empty() {
+                continue;
+            }
+            // The closing bracket of a header spread over several lines.
+            let closes_header = below.trim_start().starts_with([')', ']']);
+            if indent(below) <= level && !closes_header {
+                break;
+            }
+            end = j;
+        }
+        let mut start = i;
+        while start > 0 && lines[start - 1].trim_start().starts_with('@') {
+            start -= 1;
+        }
+        blocks.push((start + 1, end + 1));
+    }
+    blocks
+}
@@ -0,0 +1,78 @@
+//! Heuristic import extraction for `--import-graph`.
+//!
+//! Imports are read line by line from their statements, without resolving
+//! them to files: `use crate::tree::TreeNode;` is reported as `crate::tree`,
+//! `mod tree;` as `tree`, and `from .cache import ReadCache` as `.cache`.
+//! Functions imported by name (`use std::mem::take;`) are mistaken for
+//! modules, and statements spread over several lines only contribute their
+//! first line. To support another language, add its extensions to [`imports`].
+
+/// The modules `content` imports, deduplicated and sorted, or `None` if
+/// there is no import reader for `extension`.
+pub fn imports(extension: &str, content: &str) -> Option<Vec<String>> {
+    let reader: fn(&str) -> Vec<String> = match extension {
+        "rs" => rust_imports,
+        "py" | "pyi" => python_imports,
+        _ => return None,
+    };
+    let mut found = reader(content);
+    found.sort();
+    found.dedup();
+    Some(found)
+}
+
+fn rust_imports(content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for line in content.lines().map(str::trim_start) {
+        // Drop a visibility such as `pub` or `pub(crate)`.
+        let line = match line.strip_prefix("pub") {
+            Some(rest) if rest.starts_with('(') => rest.split_once(')').map_or("", |(_, r)| r),
+            Some(rest) => rest,
+            None => line,
+        }
+        .trim_start();
+        if let Some(path) = line.strip_prefix("use ") {
+            // Keep the module: `std::io::{self, Write}`, `std::fs::File` and
+            // `std::env` give `std::io`, `std::fs` and `std::env`. A capitalized last
+            // segment is taken to be a type or trait rather than a module.
+            let path = path
+                .split([';', '{', ' '])
+                .next()
+                .unwrap_or("")
+                .trim_end_matches("::");
+            let module = match path.rsplit_once("::") {
+                Some((module, item)) if item.starts_with(char::is_uppercase) || item == "*" => {
+                    module
+                }
+                _ => path,
+            };
+            if !module.is_empty() {
+                found.push(module.to_string());
+            }
+        } else if let Some(name) = line.strip_prefix("mod ") {
+            // Only declarations of modules in other files, not inline `mod x { ... }`.
+            if let Some(name) = name.trim_end().strip_suffix(';') {
+                found.push(name.trim().to_string());
+            }
+        }
+    }
+    found
+}
+
+fn python_imports(content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for line in content.lines().map(str::trim_start) {
+        if let Some(names) = line.strip_prefix("import ") {
+            for name in names.split('#').next().unwrap_or("").split(',') {
+                if let Some(module) = name.split_whitespace().next() {
+                    found.push(module.to_string());
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            if let Some((module, _)) = rest.split_once(" import") {
+                found.push(module.trim().to_string());
+            }
+        }
+    }
+    found
+}
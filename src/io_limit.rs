@@ -0,0 +1,61 @@
+//! Bounds how many files are open at once, so large trees don't run into
+//! "too many open files" on systems with a low `ulimit -n`.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+/// A counting semaphore handing out one permit per open file.
+///
+/// Reads are sequential today, so at most one permit is held at a time; the
+/// limit matters once files are read from several threads.
+pub struct OpenFileLimit {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Returns its permit to the [`OpenFileLimit`] when dropped.
+pub struct Permit<'a> {
+    limit: &'a OpenFileLimit,
+}
+
+impl OpenFileLimit {
+    pub fn new(max_open: usize) -> Self {
+        OpenFileLimit {
+            available: Mutex::new(max_open),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a file may be opened.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        Permit { limit: self }
+    }
+
+    /// [`fs::read_to_string`], holding a permit while the file is open.
+    pub fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let _permit = self.acquire();
+        fs::read_to_string(path)
+    }
+
+    /// The first `limit` bytes of a file, holding a permit while it is open.
+    pub fn read_prefix(&self, path: &Path, limit: u64) -> io::Result<Vec<u8>> {
+        let _permit = self.acquire();
+        let mut bytes = Vec::new();
+        fs::File::open(path)?.take(limit).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.limit.available.lock().unwrap() += 1;
+        self.limit.released.notify_one();
+    }
+}
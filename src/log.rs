@@ -0,0 +1,194 @@
+//! Verbosity-aware printing for everything that isn't the collected buffer itself.
+//!
+//! Output goes through `anstream`, which drops color codes when the stream
+//! isn't a terminal, `NO_COLOR` is set, or `--color never` is given.
+
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `verbosity` should currently be printed.
+pub fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+static EXPLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Turns on `--explain`, which reports what became of every walked path.
+pub fn set_explain(explain: bool) {
+    EXPLAIN.store(explain, Ordering::Relaxed);
+}
+
+pub fn explaining() -> bool {
+    EXPLAIN.load(Ordering::Relaxed)
+}
+
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Moves the lines that normally go to stdout (progress, summaries, the tree)
+/// to stderr, for `--stdout`, which prints the buffer there.
+pub fn take_stdout() {
+    STDOUT_TAKEN.store(true, Ordering::Relaxed);
+}
+
+pub fn stdout_taken() -> bool {
+    STDOUT_TAKEN.load(Ordering::Relaxed)
+}
+
+/// Where the summary counts (`Excluded 3 lockfiles.` and the like) go
+/// relative to the file tree.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SummaryPosition {
+    /// As they are computed, before the tree
+    #[default]
+    Top,
+    /// After the tree, just before the final line
+    Bottom,
+    /// Not at all
+    None,
+}
+
+static SUMMARY_POSITION: AtomicU8 = AtomicU8::new(SummaryPosition::Top as u8);
+static DEFERRED_SUMMARY: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static RECORDED_SUMMARY: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+pub fn set_summary_position(position: SummaryPosition) {
+    SUMMARY_POSITION.store(position as u8, Ordering::Relaxed);
+}
+
+/// Keeps a copy of every summary line from now on, printed or not, for
+/// `--summary-to-file`.
+pub fn record_summary() {
+    *RECORDED_SUMMARY.lock().unwrap() = Some(Vec::new());
+}
+
+/// The summary lines kept since [`record_summary`], which starts over.
+pub fn take_recorded_summary() -> Vec<String> {
+    RECORDED_SUMMARY
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+/// Prints or holds back one summary line, as the summary position says.
+pub fn summary_line(line: String) {
+    if let Some(recorded) = RECORDED_SUMMARY.lock().unwrap().as_mut() {
+        recorded.push(line.clone());
+    }
+    if !enabled(Verbosity::Normal) {
+        return;
+    }
+    match SUMMARY_POSITION.load(Ordering::Relaxed) {
+        p if p == SummaryPosition::Top as u8 => progress!("{}", highlight_numbers(&line)),
+        p if p == SummaryPosition::Bottom as u8 => DEFERRED_SUMMARY.lock().unwrap().push(line),
+        _ => {}
+    }
+}
+
+/// Prints the summary lines held back by `--summary bottom`.
+pub fn flush_summary() {
+    for line in DEFERRED_SUMMARY.lock().unwrap().drain(..) {
+        progress!("{}", highlight_numbers(&line));
+    }
+}
+
+pub const NUMBER: Style = Style::new().bold();
+pub const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+/// Highlights the words of `message` that are plain numbers, such as counts in
+/// a summary line. Numbers inside paths or other words are left alone.
+pub fn highlight_numbers(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            let start = word.len() - word.trim_start_matches('(').len();
+            let end = word.trim_end_matches([',', '.', ')', ':']).len();
+            let number = &word[start..end.max(start)];
+            if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+                format!(
+                    "{}{}{}{}{}",
+                    &word[..start],
+                    NUMBER.render(),
+                    number,
+                    NUMBER.render_reset(),
+                    &word[end..]
+                )
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Prints a line to stdout, or to stderr once `--stdout` has taken stdout.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::log::stdout_taken() {
+            anstream::eprintln!($($arg)*);
+        } else {
+            anstream::println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a progress or summary line to stdout, unless `--quiet` is given.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal) {
+            $crate::log::progress!("{}", $crate::log::highlight_numbers(&format!($($arg)*)));
+        }
+    };
+}
+
+/// Prints a summary count to stdout where `--summary` puts it, unless `--quiet` is given.
+macro_rules! summary {
+    ($($arg:tt)*) => {
+        $crate::log::summary_line(format!($($arg)*))
+    };
+}
+
+/// Prints a note about skipped input to stderr, unless `--quiet` is given.
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal) {
+            anstream::eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints a per-file diagnostic to stderr, only with `--verbose`.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Verbose) {
+            anstream::eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints what became of one path to stderr, only with `--explain`.
+macro_rules! explain {
+    ($subject:expr, $($arg:tt)*) => {
+        if $crate::log::explaining() {
+            anstream::eprintln!("{}: {}", $subject, format!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use {explain, info, notice, progress, summary, verbose};
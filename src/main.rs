@@ -1,224 +1,6911 @@
-use arboard::Clipboard;
-use clap::Parser;
+mod archive;
+mod budget;
+mod cache;
+mod clipboard;
+mod editorconfig;
+mod expand;
+mod explain;
+mod gitattributes;
+mod hgignore;
+mod hunks;
+mod imports;
+mod io_limit;
+mod log;
+mod negations;
+mod repomix;
+mod secrets;
+mod syntax;
+mod template;
+mod tokenizer;
+mod tree;
+mod unpack;
+
+use budget::{buffer_share, drop_files, files_to_drop, pick_files_to_drop, token_share};
+use cache::{CachedRead, ReadCache};
+use clap::parser::ValueSource;
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use clipboard::{ClipboardBackend, SystemClipboard};
+use editorconfig::EditorConfigs;
+use expand::expand_path;
+use gitattributes::GitAttributes;
+use globset::{GlobBuilder, GlobSetBuilder};
+use hgignore::HgIgnore;
 use ignore::types::TypesBuilder;
 use ignore::WalkBuilder;
-use std::collections::{HashMap, HashSet};
+use io_limit::OpenFileLimit;
+use log::{explain, info, notice, summary, verbose, SummaryPosition, Verbosity};
+use negations::Negations;
+use regex::{Regex, RegexBuilder};
+use repomix::format_repomix;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::ErrorKind;
+use std::io::{self, BufRead, ErrorKind, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use template::{render_template, render_xml};
+use tokenizer::{count_tokens, describe_tokens};
+use tree::{TreeNode, TreeOptions, TreeSort, TreeStyle, Uncollected};
+use walkdir::WalkDir;
+
+#[derive(Clone, Parser)]
+#[command(
+    name = "code_collector",
+    about = "Collect code files into a buffer, respecting .gitignore and filtering by extension",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    // The ways to size the parts written to --output-dir.
+    group = ArgGroup::new("split").args(["split_output", "chunk_tokens"]),
+    // The files the buffer can go to besides the clipboard.
+    group = ArgGroup::new("file_output").args(["output", "append_to", "stdout"]).multiple(true)
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The directory (or single file) to process
+    #[arg(required_unless_present_any = ["archive", "list_defaults", "batch"], value_parser = expand_path)]
+    directory: Option<String>,
+
+    /// File extensions to include (e.g., rs, py). Specify multiple times for multiple extensions.
+    /// Aliases are matched too (yaml also matches yml; see --list-defaults).
+    #[arg(
+        short,
+        long,
+        value_name = "EXTENSION",
+        use_value_delimiter = true,
+        env = "CODE_COLLECTOR_EXTENSIONS"
+    )]
+    extensions: Vec<String>,
+
+    /// Directory names to exclude wherever they appear, in addition to the built-in ones
+    #[arg(
+        short = 'x',
+        long,
+        value_name = "DIRECTORY",
+        use_value_delimiter = true,
+        env = "CODE_COLLECTOR_EXCLUDE_DIRS"
+    )]
+    exclude_dirs: Vec<String>,
+
+    /// Exclude directories whose path relative to the collected directory matches GLOB
+    /// (e.g. `frontend/build`, `packages/*/dist`), unlike -x, which matches names anywhere
+    #[arg(long = "exclude-dir-glob", value_name = "GLOB")]
+    exclude_dir_globs: Vec<String>,
+
+    /// Collect at most N levels below the directory: 1 takes only the files directly in it
+    #[arg(long, value_name = "N")]
+    max_depth: Option<NonZeroUsize>,
+
+    /// Depth limit for the collected directory DIR, overriding --max-depth when that is the
+    /// directory being collected (e.g. one of the --batch directories). Repeatable.
+    #[arg(long, value_name = "DIR=N", value_parser = parse_root_depth)]
+    depth: Vec<(String, NonZeroUsize)>,
+
+    /// Skip files that look like tests (e.g. `tests/`, `*_test.rs`, `test_*.py`, `*.spec.ts`)
+    #[arg(long)]
+    exclude_tests: bool,
+
+    /// Put the files that look like tests after the others, under a `===== Tests =====`
+    /// banner; --sort (and --group-by-extension) apply within each part
+    #[arg(
+        long,
+        conflicts_with_all = ["exclude_tests", "interleave", "prefix_lines", "template_file"]
+    )]
+    tests_separate: bool,
+
+    /// Render the collected files through a Tera template instead of the default format
+    #[arg(long, value_name = "PATH", value_parser = expand_path)]
+    template_file: Option<String>,
+
+    /// Follow symbolic links, noting each linked file's target in its header; a file reached
+    /// through several paths is collected once, under the first
+    #[arg(long)]
+    follow_links: bool,
+
+    /// Print the matched file paths separated by NUL bytes (for `xargs -0`) instead of collecting
+    /// them; like --dry-run, this reads no file, so filters that need file contents are not applied
+    #[arg(long)]
+    print0: bool,
+
+    /// List the files that would be collected, one per line, without reading them; filters
+    /// that need file contents (e.g. --grep, --dedupe-by content) are not applied
+    #[arg(long, conflicts_with = "print0")]
+    dry_run: bool,
+
+    /// With --dry-run, print a JSON array of {path, bytes, mtime, extension, would_include} instead
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+
+    /// With --dry-run, print JSON totals of files, bytes and estimated tokens (overall and per
+    /// extension) from file sizes alone, for cheap size checks in CI; with --summary-to-file,
+    /// write the summary as JSON
+    #[arg(long, conflicts_with = "json")]
+    stats_json: bool,
+
+    /// After the run, write what was collected (the summary counts, the per-extension table
+    /// and the file list) to a separate file, as plain text or, with --stats-json, JSON
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = expand_path,
+        conflicts_with_all = ["dry_run", "print0", "batch", "git_diff"]
+    )]
+    summary_to_file: Option<String>,
+
+    /// Re-serialize `.json` files compactly to save space
+    #[arg(long)]
+    minify_json: bool,
+
+    /// Collect from a .zip, .tar, .tar.gz or .tgz archive instead of a directory
+    #[arg(long, value_name = "PATH", conflicts_with = "directory", value_parser = expand_path)]
+    archive: Option<String>,
+
+    /// Only collect files whose content matches this regular expression
+    #[arg(long, value_name = "REGEX")]
+    grep: Option<String>,
+
+    /// Skip files whose content matches this regular expression, e.g. `@generated`
+    #[arg(long, value_name = "REGEX")]
+    skip_content: Option<String>,
+
+    /// With --grep, emit only the matching lines plus N lines of context around them
+    #[arg(long, value_name = "N", requires = "grep")]
+    context_lines: Option<usize>,
+
+    /// Report how many files and directories were left out by ignore rules, excludes and type filters
+    #[arg(long)]
+    show_gitignored_count: bool,
+
+    /// Print why each walked path was included or excluded to stderr, to debug filters
+    #[arg(long)]
+    explain: bool,
+
+    /// When no files match, print the directories that were walked, to check the right place
+    /// was searched
+    #[arg(long)]
+    emit_empty_tree: bool,
+
+    /// Directory to write output files into instead of copying to the clipboard
+    #[arg(long, value_name = "DIR", requires = "split", value_parser = expand_path)]
+    output_dir: Option<String>,
+
+    /// Split the buffer into parts of at most this many bytes (at file boundaries) in --output-dir,
+    /// with an index.json mapping each file to its part
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "output_dir",
+        conflicts_with = "template_file"
+    )]
+    split_output: Option<usize>,
+
+    /// Like --split-output, but limit each part to about N tokens (estimated at four
+    /// characters per token, or counted by --tokenizer), for models with a fixed context window
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "output_dir",
+        conflicts_with = "template_file"
+    )]
+    chunk_tokens: Option<NonZeroUsize>,
+
+    /// Warn when the buffer is over SIZE (e.g. 200KB), but still copy and write all of it
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        env = "CODE_COLLECTOR_WARN_BYTES"
+    )]
+    warn_bytes: Option<u64>,
+
+    /// Skip files larger than SIZE (e.g. 500KB, 2MB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Skip files with more than N lines, such as generated data tables whose short lines keep
+    /// them under --max-file-size; lines are counted once the file is read
+    #[arg(long, value_name = "N")]
+    max_file_lines: Option<usize>,
+
+    /// Keep only the first SIZE of larger files (cut at a character boundary), marked with
+    /// how much is shown, instead of the whole file; files over --max-file-size are still skipped
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    truncate_file_size: Option<u64>,
+
+    /// Size limit for one extension, overriding --max-file-size (e.g. json=10KB);
+    /// `EXT=none` lifts the limit. Repeatable.
+    #[arg(long, value_name = "EXT=SIZE", value_parser = parse_extension_size_limit)]
+    max_file_size_ext: Vec<(String, Option<u64>)>,
+
+    /// Cumulative byte budget for one extension (e.g. rs=50KB): once a file would exceed it,
+    /// that and all later files of the extension are dropped, in --sort order. Repeatable.
+    #[arg(long, value_name = "EXT=SIZE", value_parser = parse_extension_budget)]
+    max_bytes_per_ext: Vec<(String, u64)>,
+
+    /// Report each read file's encoding (from its byte order mark and UTF-8 validity), for
+    /// finding out why files are skipped as binary; doesn't change what is collected
+    #[arg(long)]
+    show_encoding: bool,
+
+    /// List the read files with CRLF or mixed line endings in the summary; doesn't change
+    /// what is collected
+    #[arg(long)]
+    eol_report: bool,
+
+    /// Open the buffer with this title, e.g. "Context for: fixing the auth bug", as a comment
+    /// (a heading with --format markdown); templates get it as `title`
+    #[arg(long, value_name = "STRING")]
+    title: Option<String>,
+
+    /// Wrap the whole buffer, title and summaries included, between `<NAME>` and `</NAME>`
+    /// lines, e.g. `--wrap-in-tag codebase`; each part gets its own tag with --split-output
+    #[arg(long, value_name = "NAME", value_parser = parse_tag_name)]
+    wrap_in_tag: Option<String>,
+
+    /// Collect at most N files of one extension (e.g. json=5), taken in --sort order. Repeatable.
+    #[arg(long, value_name = "EXT=N", value_parser = parse_extension_count)]
+    max_files_ext: Vec<(String, usize)>,
+
+    /// Prepend a comment block summarizing the source, date, file count, line count and extensions
+    #[arg(long)]
+    with_summary_header: bool,
+
+    /// File of `from=to` lines; each `from` is replaced by `to` in paths and file contents
+    #[arg(long, value_name = "FILE", value_parser = expand_path)]
+    rename_map: Option<String>,
+
+    /// Order of files in the buffer; defaults to walk order. Ties are broken by path for reproducibility.
+    #[arg(long, value_enum, value_name = "ORDER")]
+    sort: Option<SortOrder>,
+
+    /// Collect at most this many files, taken in --sort order
+    #[arg(long, value_name = "N")]
+    max_files: Option<usize>,
+
+    /// Put README files first (shallowest first), regardless of --sort
+    #[arg(long)]
+    readme_first: bool,
+
+    /// Put project config files (Cargo.toml, package.json, pyproject.toml, ...) first
+    /// (shallowest first), after READMEs with --readme-first
+    #[arg(long)]
+    config_first: bool,
+
+    /// Print every collected and skipped file
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only print warnings and errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Stop collecting (at a file boundary) before the total line count would exceed N
+    #[arg(long, value_name = "N")]
+    max_total_lines: Option<usize>,
+
+    /// Stop collecting (at a file boundary) before the file contents would exceed N tokens
+    /// (estimated at four characters per token, or counted by --tokenizer)
+    #[arg(long, value_name = "N")]
+    max_total_tokens: Option<usize>,
+
+    /// Count tokens with this model's tokenizer (e.g. gpt-4o) for --chunk-tokens,
+    /// --max-total-tokens and --interactive-budget; needs a build with the tiktoken
+    /// feature, and falls back to the estimate otherwise or for unknown models
+    #[arg(long, value_name = "MODEL")]
+    tokenizer: Option<String>,
+
+    /// Drop files until the buffer should fit in this size (e.g. 2MB), for clipboards that
+    /// fail on large copies; which files go first is set by --fit-drop
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        conflicts_with_all = ["no_clipboard", "clipboard_chunks", "split", "manifest_only"]
+    )]
+    fit_clipboard: Option<u64>,
+
+    /// Which files --fit-clipboard drops first
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHICH",
+        default_value = "largest",
+        requires = "fit_clipboard"
+    )]
+    fit_drop: FitDrop,
+
+    /// When the buffer would be over this many (estimated) tokens, list the largest files and
+    /// let you pick which to drop; without an interactive terminal the largest go first
+    #[arg(long, value_name = "TOKENS")]
+    interactive_budget: Option<u64>,
+
+    /// Skip dependency lockfiles such as Cargo.lock, package-lock.json and poetry.lock
+    #[arg(long)]
+    exclude_lockfiles: bool,
+
+    /// File listing relative paths in the order they should appear; unlisted files follow
+    #[arg(long, value_name = "PATH", value_parser = expand_path)]
+    order_file: Option<String>,
+
+    /// With --order-file, drop files that aren't listed instead of appending them
+    #[arg(long, requires = "order_file")]
+    strict_order: bool,
+
+    /// Also put an HTML version (tree and code in <pre> blocks) on the clipboard for rich-text targets
+    #[arg(long, conflicts_with = "split")]
+    html: bool,
+
+    /// Show header paths relative to the current directory instead of the collected directory
+    #[arg(long, conflicts_with = "archive")]
+    relative_from_cwd: bool,
+
+    /// Show header paths relative to the root of the enclosing git repository, wherever the
+    /// collected directory is inside it
+    #[arg(long, conflicts_with_all = ["archive", "relative_from_cwd"])]
+    relative_to_git_root: bool,
+
+    /// Path separators in file headers: the platform's own, or `/` everywhere so pastes from
+    /// Windows look the same as from Unix
+    #[arg(long, value_enum, value_name = "STYLE", default_value = "native")]
+    path_style: PathStyle,
+
+    /// Rewrite the paths shown in headers and the tree, not the files read; repeatable, applied
+    /// in the order given
+    #[arg(long, value_enum, value_name = "TRANSFORM")]
+    path_transform: Vec<PathTransform>,
+
+    /// Make the output byte-identical across runs and machines: sort by path, leave out dates
+    /// and modification times, convert CRLF line endings to LF and name the tree root after
+    /// the collected directory
+    #[arg(long, conflicts_with_all = ["sort", "modified_within"])]
+    deterministic: bool,
+
+    /// Keep only the first N lines of each file
+    #[arg(long, value_name = "N", conflicts_with = "tail")]
+    head: Option<usize>,
+
+    /// Keep only the last N lines of each file
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Keep files marked `linguist-generated` or `linguist-vendored` in .gitattributes (skipped by default)
+    #[arg(long)]
+    include_generated: bool,
+
+    /// Skip files whose first lines carry a generated-code comment such as
+    /// `// Code generated by ...; DO NOT EDIT.` or `# Auto-generated`. Independent of
+    /// the .gitattributes check that --include-generated turns off
+    #[arg(long)]
+    exclude_generated_by_comment: bool,
+
+    /// Baseline directory; files identical to their counterpart there are emitted as a header only
+    #[arg(long, value_name = "DIR", value_parser = expand_path)]
+    diff_against: Option<String>,
+
+    /// Annotate directories in the tree with how many collected files they contain
+    #[arg(long)]
+    tree_file_counts: bool,
+
+    /// Drop the directory prefix shared by all collected files from the headers
+    #[arg(long)]
+    strip_prefix_common: bool,
+
+    /// Note `[no final newline]` in the header of files that don't end with a newline
+    #[arg(long)]
+    mark_no_final_newline: bool,
+
+    /// Skip files that look minified (very long lines), such as bundled JS or CSS
+    #[arg(long)]
+    exclude_minified: bool,
+
+    /// Only collect files modified within this long ago (e.g. 2h, 3d, 1w); sorts newest first unless --sort is given
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        conflicts_with = "archive"
+    )]
+    modified_within: Option<Duration>,
+
+    /// Only collect files modified after this one was (e.g. a build artifact), or after a
+    /// UTC timestamp such as 2024-05-01 or 2024-05-01T09:30:00Z
+    #[arg(
+        long,
+        value_name = "PATH_OR_TIMESTAMP",
+        value_parser = parse_newer_than,
+        conflicts_with = "archive"
+    )]
+    newer_than: Option<SystemTime>,
+
+    /// Collect every file regardless of extension, leaving it to binary detection to skip
+    /// non-text files. This is also what happens when no -e is given; the flag sets aside
+    /// CODE_COLLECTOR_EXTENSIONS too, and can't be combined with -e.
+    #[arg(long)]
+    all_text: bool,
+
+    /// Print the built-in directory excludes and file type definitions, then exit
+    #[arg(long)]
+    list_defaults: bool,
+
+    /// Maximum number of files (and directories being walked) to keep open at once
+    #[arg(long, value_name = "N", default_value = "64")]
+    max_open_files: NonZeroUsize,
+
+    /// Print how long the walk, file reads, formatting and output steps took, to stderr
+    #[arg(long)]
+    timing: bool,
+
+    /// Record the collected paths and content hashes in FILE, first reporting on stderr which
+    /// files were added, removed or changed since FILE was last written
+    #[arg(long, value_name = "FILE", value_parser = expand_path)]
+    snapshot: Option<String>,
+
+    /// Record the buffer's size in FILE, keeping a short history per collected directory, and
+    /// report how much it grew or shrank since the last run
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = expand_path,
+        conflicts_with_all = ["dry_run", "print0", "split", "manifest_only"]
+    )]
+    size_history: Option<String>,
+
+    /// Print only a manifest of the collected files to stdout, their paths, SHA-256 hashes and
+    /// sizes after every filter, as `path<TAB>hash<TAB>bytes` lines or a JSON array; nothing is
+    /// copied or written
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "tsv",
+        conflicts_with_all = ["dry_run", "print0", "file_output", "split", "git_diff"]
+    )]
+    manifest_only: Option<ManifestFormat>,
+
+    /// Append a short SHA-256 of each file's content to its header
+    #[arg(long)]
+    with_hash: bool,
+
+    /// Group files by extension, each group under a banner comment (a `##` heading per language
+    /// with --format markdown); --sort applies within groups
+    #[arg(long)]
+    group_by_extension: bool,
+
+    /// Also honor .npmignore files (gitignore syntax, taking precedence over .gitignore)
+    #[arg(long)]
+    use_npmignore: bool,
+
+    /// Also honor .dockerignore files (read as gitignore syntax, taking precedence over .gitignore)
+    #[arg(long)]
+    use_dockerignore: bool,
+
+    /// Also honor the .hgignore file at the root of the collected directory, as Mercurial reads it
+    #[arg(long)]
+    respect_hgignore: bool,
+
+    /// Ignore the .gitignore files (and .git/info/exclude) above the collected directory;
+    /// by default they apply, as in git
+    #[arg(long)]
+    no_parent_ignores: bool,
+
+    /// Collect gitignored files with this extension anyway (e.g. sql for ignored dumps), keeping
+    /// everything else ignored; -e and the directory excludes still apply. Repeatable.
+    #[arg(
+        long,
+        value_name = "EXTENSION",
+        use_value_delimiter = true,
+        conflicts_with = "archive"
+    )]
+    unignore_ext: Vec<String>,
+
+    /// Emit only the comment lines (including doc comments and Python docstrings) of each file.
+    /// Heuristic: trailing comments after code are dropped. Files without comments are skipped.
+    #[arg(long)]
+    collect_comments_only: bool,
+
+    /// Also write the doc comments of the collected files (`///` and `//!` in Rust, docstrings
+    /// in Python, `/** */` in other C-family languages) to PATH; the buffer keeps the full code
+    #[arg(long, value_name = "PATH", value_parser = expand_path)]
+    docstrings_output: Option<String>,
+
+    /// Shorten lines longer than N characters, as chosen by --line-length-mode
+    #[arg(long, value_name = "N")]
+    max_line_length: Option<NonZeroUsize>,
+
+    /// How --max-line-length shortens long lines
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value = "truncate",
+        requires = "max_line_length"
+    )]
+    line_length_mode: LineLengthMode,
+
+    /// Rewrite leading indentation with tabs or spaces: tabs, spaces, or tabs:N / spaces:N
+    /// for a tab width of N (default 4). Whitespace inside lines is untouched.
+    #[arg(long, value_name = "MODE", value_parser = parse_indent)]
+    indent: Option<IndentMode>,
+
+    /// Match excluded directory names and ignore-file patterns case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Also write the buffer to this file
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = expand_path,
+        conflicts_with = "split"
+    )]
+    output: Option<String>,
+
+    /// Append the buffer to this file (creating it if needed) under a banner naming the
+    /// collected directory, to build up one context file over several runs
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = expand_path,
+        conflicts_with = "split"
+    )]
+    append_to: Option<String>,
+
+    /// Also print the buffer to stdout, e.g. for a log, moving the progress lines, summaries
+    /// and tree to stderr
+    #[arg(long, conflicts_with_all = ["split", "dry_run", "print0", "batch"])]
+    stdout: bool,
+
+    /// Don't copy the buffer to the clipboard (use with --output, --append-to or --stdout)
+    #[arg(long, requires = "file_output", conflicts_with = "html")]
+    no_clipboard: bool,
+
+    /// Warn instead of failing when the clipboard can't be set, e.g. over SSH without a
+    /// display, so scripts relying on --output still succeed
+    #[arg(long, conflicts_with = "no_clipboard")]
+    exit_zero_on_clipboard_error: bool,
+
+    /// List the collected files as flat, sorted paths instead of drawing a tree
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "tree_depth",
+            "tree_file_counts",
+            "seed_tree_from",
+            "show_binaries_in_tree",
+            "collapse_single_child_dirs"
+        ]
+    )]
+    compact_tree: bool,
+
+    /// Characters used to draw the file tree
+    #[arg(long, value_enum, value_name = "STYLE", default_value = "unicode")]
+    tree_style: TreeStyle,
+
+    /// Order of names in the file tree, and in the buffer under --sort path
+    #[arg(long, value_enum, value_name = "ORDER", default_value = "case")]
+    tree_sort: TreeSort,
+
+    /// Also write the file tree as nested JSON objects to PATH (`-` for stdout), for tools
+    /// that draw the tree themselves
+    #[arg(long, value_name = "PATH", value_parser = expand_path, conflicts_with = "stdout")]
+    tree_json: Option<String>,
+
+    /// Where to print the summary counts (e.g. `Excluded 3 lockfiles.`): before the tree,
+    /// after it, or not at all
+    #[arg(long, value_enum, value_name = "WHERE", default_value = "top")]
+    summary: SummaryPosition,
+
+    /// Print at most N files of the tree to the terminal, then how many were left out;
+    /// the tree in the buffer stays complete
+    #[arg(long, value_name = "N")]
+    max_tree_entries: Option<NonZeroUsize>,
+
+    /// Keep the lines of the tree printed to the terminal within this many columns, cutting
+    /// long names short with an ellipsis, e.g. `--tree-width "$COLUMNS"`; the buffer's tree
+    /// and the headers keep full names
+    #[arg(long, value_name = "COLUMNS", conflicts_with = "compact_tree")]
+    tree_width: Option<NonZeroUsize>,
+
+    /// Draw the tree only N levels deep, showing deeper directories as `(+K files)`;
+    /// every file is still collected
+    #[arg(long, value_name = "N")]
+    tree_depth: Option<NonZeroUsize>,
+
+    /// Shell command each file's content is piped through (stdin to stdout); its path is in
+    /// $CODE_COLLECTOR_PATH. Runs once per file, so keep it fast on large trees.
+    #[arg(long, value_name = "CMD")]
+    content_filter: Option<String>,
+
+    /// Shell command the whole assembled buffer is piped through (stdin to stdout) before it
+    /// is copied or written, e.g. a summarizer or compressor
+    #[arg(long, value_name = "CMD", conflicts_with = "split")]
+    post_process: Option<String>,
+
+    /// Abort if --post-process fails, instead of keeping the unprocessed buffer
+    #[arg(long, requires = "post_process")]
+    strict: bool,
+
+    /// File listing extra relative paths to show in the tree as `(not collected)`, e.g. binaries
+    #[arg(long, value_name = "FILE", value_parser = expand_path)]
+    seed_tree_from: Option<String>,
+
+    /// Show the files skipped as binary in the tree, marked `(binary)`, without their content
+    #[arg(long)]
+    show_binaries_in_tree: bool,
+
+    /// Show chains of directories that each hold a single directory as one `a/b/c` node in the tree
+    #[arg(long)]
+    collapse_single_child_dirs: bool,
+
+    /// Layout of the buffer [default: comments, or markdown for an --output file ending in
+    /// .md or .markdown]
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with = "template_file"
+    )]
+    format: Option<OutputFormat>,
+
+    /// List every line containing a --todo-markers marker, with its file and line number
+    #[arg(long)]
+    highlight_todos: bool,
+
+    /// Print a table of the collected files per extension to stderr: count, size and share of
+    /// the total size
+    #[arg(long, conflicts_with = "dry_run")]
+    summary_table: bool,
+
+    /// Prepend a "Dependencies:" comment listing the modules each Rust and Python file
+    /// imports, read heuristically from its `use` / `import` lines
+    #[arg(long)]
+    import_graph: bool,
+
+    /// With --highlight-todos, also prepend the list to the buffer as comments
+    #[arg(long, requires = "highlight_todos")]
+    todos_in_buffer: bool,
+
+    /// Prepend a "Contents:" comment listing each directory that holds collected files, with
+    /// its file count per extension, as a table of contents for large buffers
+    #[arg(long)]
+    toc: bool,
+
+    /// Words that mark a TODO for --highlight-todos (matched case-sensitively as whole words)
+    #[arg(
+        long,
+        value_name = "MARKER",
+        use_value_delimiter = true,
+        default_value = "TODO,FIXME,HACK"
+    )]
+    todo_markers: Vec<String>,
+
+    /// Read directories from stdin, one per line, collecting each into `<name>.txt` in the
+    /// current directory instead of the clipboard
+    #[arg(long, conflicts_with_all = ["directory", "archive", "file_output", "split", "print0", "dry_run"])]
+    batch: bool,
+
+    /// Instead of collecting, write the files of a buffer in the comments format back out
+    /// under DIRECTORY; SRC is a file, `-` for stdin or `@clipboard`. Honors --dry-run.
+    #[arg(
+        long,
+        value_name = "SRC",
+        conflicts_with_all = ["archive", "batch", "git_diff", "print0", "file_output", "split"]
+    )]
+    unpack: Option<String>,
+
+    /// Let --unpack replace existing files that differ from the buffer's copy
+    #[arg(long, requires = "unpack")]
+    overwrite: bool,
+
+    /// Prepend a comment block with the git remote, branch and commit of the collected directory
+    #[arg(long, conflicts_with = "archive")]
+    prepend_gitinfo: bool,
+
+    /// Prepend the project's AI instruction files (.cursorrules, .cursor/rules/*,
+    /// .github/copilot-instructions.md), each under a header
+    #[arg(long, conflicts_with = "archive")]
+    include_ai_rules: bool,
+
+    /// Collect at most N files from any single directory, taken in --sort order (path order by default)
+    #[arg(long, value_name = "N")]
+    max_matches_per_dir: Option<usize>,
+
+    /// Collect at most N files with the same file name (e.g. `__init__.py`, `mod.rs`), taken in
+    /// --sort order (path order by default)
+    #[arg(long, value_name = "N")]
+    max_per_basename: Option<usize>,
+
+    /// Emit only lines START-END of FILE (relative to the collected directory); repeatable
+    #[arg(long, value_name = "FILE:START-END", value_parser = parse_line_range)]
+    line_range: Vec<LineRange>,
+
+    /// When to color the tree and summary output (the collected buffer is never colored)
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorWhen,
+
+    /// Warn about JSON, TOML, YAML and Rust files that fail to parse
+    #[arg(long)]
+    validate_syntax: bool,
+
+    /// Like --validate-syntax, but also leave the files that fail to parse out
+    #[arg(long)]
+    skip_invalid_syntax: bool,
+
+    /// Format for the clipboard copy, overriding --format
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with_all = ["template_file", "no_clipboard"]
+    )]
+    clipboard_format: Option<OutputFormat>,
+
+    /// Format for the --output and --append-to files and --stdout, overriding --format
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        requires = "file_output",
+        conflicts_with = "template_file"
+    )]
+    file_format: Option<OutputFormat>,
+
+    /// Walk into git submodules (detected via .gitmodules or a nested `.git` file)
+    #[arg(long, conflicts_with = "exclude_submodules")]
+    include_submodules: bool,
+
+    /// Skip git submodules (the default)
+    #[arg(long)]
+    exclude_submodules: bool,
+
+    /// Keep only the first file (in --sort order) of each group of duplicates
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    dedupe_by: Option<DedupeBy>,
+
+    /// Number of trailing path components compared by --dedupe-by path-suffix
+    #[arg(long, value_name = "N", default_value = "2")]
+    dedupe_suffix_len: usize,
+
+    /// With --dedupe-by content, compare files with all whitespace removed, so reformatted
+    /// copies count as duplicates; the first one is still emitted verbatim
+    #[arg(long, requires = "dedupe_by")]
+    dedupe_ignore_whitespace: bool,
+
+    /// Print sizes as exact byte counts instead of e.g. `4.2 KB`
+    #[arg(long)]
+    raw_bytes: bool,
+
+    /// Abort without producing a buffer if any file looks like it contains a credential
+    #[arg(long)]
+    fail_on_secret: bool,
+
+    /// Show the collected directory's name as the root of the tree
+    #[arg(long)]
+    relative_tree_root: bool,
+
+    /// Treat files with extension EXT as language LANG (e.g. txt=python) for comment style and
+    /// Markdown code fences; repeatable
+    #[arg(long, value_name = "EXT=LANG", value_parser = parse_lang_mapping)]
+    lang_map: Vec<(String, String)>,
+
+    /// Remove a leading comment block that mentions a copyright or license
+    #[arg(long)]
+    strip_license_headers: bool,
+
+    /// Directory caching file reads by path, mtime and size, so unchanged files aren't re-read
+    #[arg(long, value_name = "DIR", value_parser = expand_path)]
+    cache: Option<String>,
+
+    /// Empty the --cache directory before collecting
+    #[arg(long, requires = "cache")]
+    clear_cache: bool,
+
+    /// Close each file with an `end <path>` comment in the same style as its header
+    #[arg(long)]
+    with_footer: bool,
+
+    /// Write file headers (and footers) as `//path` rather than `// path`
+    #[arg(long)]
+    no_header_space: bool,
+
+    /// End the buffer right after the last file instead of with the usual blank separator lines
+    #[arg(long)]
+    no_trailing_separator: bool,
+
+    /// Header line for files with extension EXT, e.g. `md=# {path}`, instead of a comment;
+    /// `{path}` is replaced by the path. Repeatable.
+    #[arg(long, value_name = "EXT=TEMPLATE", value_parser = parse_header_template)]
+    header_template_ext: Vec<(String, String)>,
+
+    /// For runs of consecutive files in the same directory, print the directory once as a
+    /// `// dir/` banner and head each file with just its name. Changes the header format.
+    #[arg(long, conflicts_with = "prefix_lines")]
+    merge_adjacent_blank_headers: bool,
+
+    /// Head .rs files under `src/` with their module path (`crate::foo::bar`) instead of their
+    /// file path; other files, and those in `src/bin/`, keep theirs
+    #[arg(long, conflicts_with_all = ["prefix_lines", "merge_adjacent_blank_headers"])]
+    rust_module_headers: bool,
+
+    /// Lay the buffer out by directory: each directory's part of the tree, then its files in
+    /// --sort order. Applies to the comments and markdown formats
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "prefix_lines",
+            "template_file",
+            "merge_adjacent_blank_headers",
+            "group_by_extension",
+            "compact_tree",
+            "split",
+        ]
+    )]
+    interleave: bool,
+
+    /// Instead of a header per file, prefix every line with its file and line number
+    /// (`src/main.rs:42: line`), for searching the buffer line by line
+    #[arg(long, conflicts_with_all = ["template_file", "split"])]
+    prefix_lines: bool,
+
+    /// Apply `insert_final_newline` from the project's .editorconfig files and warn about
+    /// files that don't match their declared `charset`
+    #[arg(long)]
+    respect_editorconfig: bool,
+
+    /// Put files of at most --flatten-max-lines lines on their header line, e.g.
+    /// `// .nvmrc: 20`, to save space. For reading only: such files can't be split back out.
+    #[arg(long)]
+    flatten_single_line_files: bool,
+
+    /// Longest file, in lines, that --flatten-single-line-files puts on its header line;
+    /// the lines are joined with ` | `
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        requires = "flatten_single_line_files"
+    )]
+    flatten_max_lines: NonZeroUsize,
+
+    /// Only collect files tracked by git (per `git ls-files`), leaving out untracked scratch files
+    #[arg(long, conflicts_with = "archive")]
+    tracked_only: bool,
+
+    /// Collect only the files changed in the working tree, or in RANGE (e.g. main..HEAD), and of
+    /// Rust and Python files only the functions around the changed lines
+    #[arg(
+        long,
+        value_name = "RANGE",
+        num_args = 0..=1,
+        conflicts_with_all = ["archive", "git_diff", "line_range"]
+    )]
+    changed_hunks: Option<Option<String>>,
+
+    /// Put the unified diff of the working tree, or of RANGE (e.g. main..HEAD), into the buffer
+    /// instead of whole files; -e and -x are passed to git as pathspecs
+    #[arg(
+        long,
+        value_name = "RANGE",
+        num_args = 0..=1,
+        conflicts_with_all = ["archive", "batch", "dry_run", "print0", "split"]
+    )]
+    git_diff: Option<Option<String>>,
+
+    /// Only collect the files listed in FILE, one path per line relative to the collected
+    /// directory; `-` reads the list from stdin (e.g. `git diff --name-only | code_collector . --files-from -`)
+    #[arg(long, value_name = "FILE", value_parser = expand_path, conflicts_with = "batch")]
+    files_from: Option<String>,
+
+    /// Collect exactly the files (and line ranges) listed in a JSON FILE, without walking the
+    /// directory: `[{"path": "src/main.rs", "ranges": [[1, 40]]}]`, paths relative to it
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = expand_path,
+        conflicts_with_all = ["archive", "files_from", "changed_hunks"]
+    )]
+    input_json: Option<String>,
+
+    /// After collecting, list the extensions present but left out by -e, with file counts
+    #[arg(long, requires = "extensions")]
+    report_unsupported_extensions: bool,
+
+    /// Clipboard to use on Linux, for desktops where the default one doesn't reach the
+    /// pasting app; `wayland` needs `wl-copy` (from wl-clipboard)
+    #[arg(long, value_enum, value_name = "BACKEND", default_value = "auto")]
+    clipboard_backend: ClipboardBackend,
+
+    /// Copy the buffer to the clipboard in parts of at most this many bytes (at line breaks),
+    /// pressing Enter for each next part; needs an interactive terminal
+    #[arg(long, value_name = "BYTES", conflicts_with_all = ["html", "no_clipboard"])]
+    clipboard_chunks: Option<NonZeroUsize>,
+
+    /// Comment prefix for headers and markers of files whose extension has no known comment
+    /// syntax (`//` by default), e.g. `#` for mostly shell-like configs
+    #[arg(long, value_name = "PREFIX", value_parser = parse_comment_prefix)]
+    default_comment: Option<String>,
+}
+
+/// The `--default-comment` prefix, set once at startup.
+static DEFAULT_COMMENT: OnceLock<&'static str> = OnceLock::new();
+
+enum CommentStyle {
+    Line(&'static str),
+    Block(&'static str, &'static str),
+}
+
+impl CommentStyle {
+    /// Wraps `text` in a single-line comment of this style.
+    fn comment(&self, text: &str) -> String {
+        self.comment_with(text, " ")
+    }
+
+    /// Like [`CommentStyle::comment`], with `space` between the delimiters and `text`.
+    fn comment_with(&self, text: &str, space: &str) -> String {
+        match self {
+            CommentStyle::Line(prefix) => format!("{}{}{}", prefix, space, text),
+            CommentStyle::Block(start, end) => {
+                format!("{}{}{}{}{}", start, space, text, space, end)
+            }
+        }
+    }
+}
+
+fn get_comment_syntax(extension: &str) -> CommentStyle {
+    match extension {
+        "rs" | "js" | "ts" | "c" | "h" | "cpp" | "hpp" | "java" | "cs" | "go" | "swift" | "kt"
+        | "kts" => CommentStyle::Line("//"),
+        "py" | "sh" | "yaml" | "yml" | "toml" | "ini" | "rb" | "pl" | "r" | "php" | "ps1"
+        | "makefile" => CommentStyle::Line("#"),
+        "html" | "xml" | "xhtml" => CommentStyle::Block("<!--", "-->"),
+        "css" => CommentStyle::Block("/*", "*/"),
+        _ => CommentStyle::Line(DEFAULT_COMMENT.get().copied().unwrap_or("//")),
+    }
+}
+
+fn parse_comment_prefix(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.chars().any(char::is_whitespace) {
+        return Err("expected a comment prefix without spaces, e.g. #".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// Resolves a `--lang-map` language name (or an extension standing for one)
+/// to the extension whose comment syntax and Markdown tag it uses.
+fn language_extension(language: &str) -> Option<&'static str> {
+    let extension = match language.to_lowercase().as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "c" | "h" => "c",
+        "c++" | "cpp" | "hpp" => "cpp",
+        "java" => "java",
+        "c#" | "csharp" | "cs" => "cs",
+        "go" => "go",
+        "swift" => "swift",
+        "kotlin" | "kt" | "kts" => "kt",
+        "shell" | "bash" | "sh" => "sh",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" => "ini",
+        "ruby" | "rb" => "rb",
+        "perl" | "pl" => "pl",
+        "r" => "r",
+        "php" => "php",
+        "powershell" | "ps1" => "ps1",
+        "makefile" | "make" => "makefile",
+        "html" | "xhtml" => "html",
+        "xml" => "xml",
+        "css" => "css",
+        "markdown" | "md" => "md",
+        "json" => "json",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/// Parses a size like `500`, `10KB`, `1.5M` or `2GiB` (powers of 1024, as
+/// sizes are printed).
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size {:?}, expected e.g. 500, 10KB or 2MB", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        other => return Err(format!("unknown size unit {:?}", other)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses `EXT=SIZE`, where a SIZE of `none` lifts the limit for the extension.
+fn parse_extension_size_limit(value: &str) -> Result<(String, Option<u64>), String> {
+    let (extension, size) = value
+        .split_once('=')
+        .ok_or("expected EXT=SIZE, e.g. json=10KB")?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        return Err("expected EXT=SIZE, e.g. json=10KB".to_string());
+    }
+    let limit = match size.trim() {
+        "none" => None,
+        size => Some(parse_size(size)?),
+    };
+    Ok((extension, limit))
+}
+
+/// Parses `EXT=SIZE` for `--max-bytes-per-ext`.
+fn parse_extension_budget(value: &str) -> Result<(String, u64), String> {
+    match parse_extension_size_limit(value)? {
+        (extension, Some(budget)) => Ok((extension, budget)),
+        (_, None) => Err("expected EXT=SIZE, e.g. rs=50KB".to_string()),
+    }
+}
+
+/// Parses `EXT=TEMPLATE` for `--header-template-ext`; the template must use `{path}`.
+fn parse_header_template(value: &str) -> Result<(String, String), String> {
+    let (extension, template) = value
+        .split_once('=')
+        .ok_or("expected EXT=TEMPLATE, e.g. md=# {path}")?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        return Err("expected EXT=TEMPLATE, e.g. md=# {path}".to_string());
+    }
+    if !template.contains("{path}") {
+        return Err(format!("template {:?} doesn't contain {{path}}", template));
+    }
+    Ok((extension, template.to_string()))
+}
+
+/// Parses `EXT=N` for `--max-files-ext`.
+fn parse_extension_count(value: &str) -> Result<(String, usize), String> {
+    let (extension, count) = value.split_once('=').ok_or("expected EXT=N, e.g. json=5")?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        return Err("expected EXT=N, e.g. json=5".to_string());
+    }
+    let count = count
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid file count {:?}", count.trim()))?;
+    Ok((extension, count))
+}
+
+/// Accepts an XML-style tag name: a letter or `_`, then letters, digits, `_`, `-` or `.`.
+fn parse_tag_name(value: &str) -> Result<String, String> {
+    let mut chars = value.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!("{:?} isn't a tag name, e.g. codebase", value))
+    }
+}
+
+fn parse_root_depth(value: &str) -> Result<(String, NonZeroUsize), String> {
+    let (dir, depth) = value
+        .rsplit_once('=')
+        .ok_or("expected DIR=N, e.g. vendor=1")?;
+    let depth = depth
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid depth {:?}", depth.trim()))?;
+    Ok((expand_path(dir.trim())?, depth))
+}
+
+/// A file's modification time, or an ISO 8601 date or date and time in UTC.
+/// An existing file wins over a timestamp of the same name.
+fn parse_newer_than(value: &str) -> Result<SystemTime, String> {
+    let path = expand_path(value)?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        return metadata
+            .modified()
+            .map_err(|e| format!("no modification time for {}: {}", path, e));
+    }
+    let timestamp = if value.len() == 10 {
+        format!("{}T00:00:00Z", value)
+    } else {
+        value.to_string()
+    };
+    humantime::parse_rfc3339_weak(&timestamp).map_err(|_| {
+        format!(
+            "{:?} is neither an existing file nor a timestamp like 2024-05-01T09:30:00Z",
+            value
+        )
+    })
+}
+
+fn parse_lang_mapping(value: &str) -> Result<(String, String), String> {
+    let (extension, language) = value
+        .split_once('=')
+        .ok_or("expected EXT=LANG, e.g. txt=python")?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() || language.trim().is_empty() {
+        return Err("expected EXT=LANG, e.g. txt=python".to_string());
+    }
+    Ok((extension, language.trim().to_string()))
+}
+
+#[derive(Clone, Subcommand)]
+enum Command {
+    /// Print a shell completion script to stdout
+    Completions {
+        /// The shell to generate completions for
+        shell: Shell,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorWhen {
+    /// Color when printing to a terminal, unless NO_COLOR is set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ManifestFormat {
+    /// `path<TAB>sha256<TAB>bytes` lines
+    Tsv,
+    /// An array of {path, sha256, bytes}
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Each file under a comment header naming its path
+    Comments,
+    /// The tree, then each file under a `##` heading in a fenced code block
+    Markdown,
+    /// The `plain` style of repomix: summary, directory structure, then `File:` sections
+    Repomix,
+    /// The tree, then each file in a `<file>` element, as in templates/xml.tera
+    Xml,
+    /// An object with the tree and an array of files, each with its path and content
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FitDrop {
+    /// The largest files, so the fewest are dropped
+    Largest,
+    /// The smallest files, keeping the big ones whole
+    Smallest,
+    /// The files at the end of the buffer, as --sort, --readme-first and the like order it
+    Last,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DedupeBy {
+    /// Files with identical (processed) content
+    Content,
+    /// Files with the same name, wherever they are
+    Filename,
+    /// Files whose last --dedupe-suffix-len path components match
+    PathSuffix,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineLengthMode {
+    /// Cut long lines off, marking the cut with `…`
+    Truncate,
+    /// Hard-wrap long lines onto several lines
+    Wrap,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum PathStyle {
+    /// As the platform writes them (`src\main.rs` on Windows)
+    Native,
+    /// Always with forward slashes (`src/main.rs`)
+    Posix,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PathTransform {
+    /// All lowercase, for matching paths case-insensitively downstream
+    Lowercase,
+    /// Just the file name, dropping its directories
+    BasenameOnly,
+    /// Forward slashes, as --path-style posix
+    Posix,
+}
+
+impl PathTransform {
+    fn apply(self, path: &str) -> String {
+        match self {
+            PathTransform::Lowercase => path.to_lowercase(),
+            PathTransform::BasenameOnly => path
+                .rsplit(['/', std::path::MAIN_SEPARATOR])
+                .next()
+                .unwrap_or(path)
+                .to_string(),
+            PathTransform::Posix => path.replace(std::path::MAIN_SEPARATOR, "/"),
+        }
+    }
+}
+
+/// The module path of a Rust file from its path, e.g. `crate::foo::bar` for
+/// `src/foo/bar.rs` or `src/foo/bar/mod.rs`, and `crate` for `src/lib.rs`
+/// and `src/main.rs`. `in_src` says the path is already relative to `src/`.
+/// `None` for files outside `src/`, in `src/bin/`, or whose names aren't
+/// identifiers.
+fn rust_module_path(path: &Path, in_src: bool) -> Option<String> {
+    if path.extension()? != "rs" {
+        return None;
+    }
+    let components: Vec<String> = path
+        .with_extension("")
+        .components()
+        .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let start = if in_src {
+        0
+    } else {
+        let dirs = &components[..components.len() - 1];
+        dirs.iter().position(|comp| comp == "src")? + 1
+    };
+    let mut modules = &components[start..];
+    if modules.first().is_some_and(|first| first == "bin") {
+        return None;
+    }
+    if modules.last().is_some_and(|last| last == "mod")
+        || (modules.len() == 1 && (modules[0] == "lib" || modules[0] == "main"))
+    {
+        modules = &modules[..modules.len() - 1];
+    }
+    let is_identifier = |name: &String| {
+        name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    if !modules.iter().all(is_identifier) {
+        return None;
+    }
+    Some(
+        std::iter::once("crate")
+            .chain(modules.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join("::"),
+    )
+}
+
+/// Applies each of `transforms` to `path` in turn.
+fn transform_path(path: &str, transforms: &[PathTransform]) -> String {
+    transforms
+        .iter()
+        .fold(path.to_string(), |path, transform| transform.apply(&path))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortOrder {
+    /// Alphabetically by relative path
+    Path,
+    /// Most recently modified first
+    MtimeDesc,
+}
+
+/// Where a candidate file's content comes from.
+enum FileSource {
+    /// A file on disk, read lazily once it has passed the path filters.
+    Disk(PathBuf),
+    /// The raw bytes of an archive entry.
+    Archive(Vec<u8>),
+}
+
+/// A file yielded by the walker or an archive, before any filtering.
+struct Candidate {
+    relative_path: PathBuf,
+    source: FileSource,
+}
+
+/// A file that made it through all filters, ready to be written to the buffer.
+pub struct CollectedFile {
+    /// Path relative to the collected directory, as used for the tree.
+    relative_path: PathBuf,
+    /// Path shown in the file's header.
+    display_path: String,
+    extension: String,
+    /// Extension whose comment syntax and Markdown tag the file uses; differs
+    /// from `extension` only through `--lang-map`.
+    language: String,
+    content: String,
+    /// Where the file really lives, if it was reached through a symlink.
+    link_target: Option<PathBuf>,
+    /// Last modification time, when known (archive entries have none).
+    modified: Option<SystemTime>,
+    /// Identical to the file in the --diff-against baseline; only the header is emitted.
+    unchanged: bool,
+    /// Bracketed annotations appended to the header, e.g. `no final newline`.
+    notes: Vec<String>,
+    /// Hex SHA-256 of the file as read, before any transformation (`--with-hash`).
+    sha256: Option<String>,
+    /// Marker lines found with `--highlight-todos`.
+    todos: Vec<Todo>,
+}
+
+impl CollectedFile {
+    /// Formats the file as a comment header naming its path, followed by its content.
+    fn format(&self, options: &FormatOptions) -> String {
+        self.format_as(&self.display_path, options)
+    }
+
+    /// Formats the file with `path` in its header and footer in place of its display path.
+    fn format_as(&self, path: &str, options: &FormatOptions) -> String {
+        let mut full_relative_path = path.to_string();
+        if let Some(target) = &self.link_target {
+            full_relative_path.push_str(&format!(" -> {}", target.to_string_lossy()));
+        }
+        if self.unchanged {
+            full_relative_path.push_str(" [unchanged]");
+        }
+        for note in &self.notes {
+            full_relative_path.push_str(&format!(" [{}]", note));
+        }
+        if let Some(hash) = &self.sha256 {
+            full_relative_path.push_str(&format!(" [sha256:{}..]", &hash[..SHORT_HASH_LEN]));
+        }
+        let mut file_content = String::new();
+        let space = if options.header_space { " " } else { "" };
+
+        let flatten = options.flatten_max_lines.is_some_and(|max| {
+            !self.unchanged
+                && !self.content.trim().is_empty()
+                && self.content.lines().count() <= max
+        });
+        let template = options.header_template(&self.extension);
+        if flatten {
+            let lines: Vec<&str> = self.content.lines().collect();
+            let inline = format!("{}: {}", full_relative_path, lines.join(" | "));
+            match template {
+                Some(template) => file_content.push_str(&template.replace("{path}", &inline)),
+                None => file_content
+                    .push_str(&get_comment_syntax(&self.language).comment_with(&inline, space)),
+            }
+            file_content.push('\n');
+            return file_content;
+        }
+
+        match (template, get_comment_syntax(&self.language)) {
+            (Some(template), _) => {
+                file_content.push_str(&template.replace("{path}", &full_relative_path));
+                file_content.push('\n');
+            }
+            (None, CommentStyle::Line(prefix)) => {
+                file_content.push_str(&format!("{}{}{}\n", prefix, space, full_relative_path));
+            }
+            (None, CommentStyle::Block(start, end)) => {
+                file_content.push_str(&format!(
+                    "{}{}{}\n{}\n",
+                    start, space, full_relative_path, end
+                ));
+            }
+        }
+
+        if self.unchanged {
+            file_content.push('\n');
+            return file_content;
+        }
+
+        file_content.push_str(&self.content);
+        if options.footers {
+            if !self.content.ends_with('\n') {
+                file_content.push('\n');
+            }
+            let end_marker = format!("end {}", path);
+            file_content
+                .push_str(&get_comment_syntax(&self.language).comment_with(&end_marker, space));
+            file_content.push('\n');
+        }
+        file_content.push_str("\n\n");
+        file_content
+    }
+}
+
+/// Strips the Windows verbatim prefix (`\\?\`) when the path is valid without it;
+/// paths over the legacy 260 character limit keep it so they stay readable.
+///
+/// `canonicalize` returns verbatim paths on Windows, which never share a prefix
+/// with the ordinary paths the walker yields. Elsewhere this is a no-op.
+fn normalize_path(path: &Path) -> PathBuf {
+    dunce::simplified(path).to_path_buf()
+}
+
+/// Expresses `path` relative to `base`, walking up with `..` where needed.
+///
+/// Both paths must be absolute (or both relative); returns `None` when they
+/// share no common root, e.g. different drives on Windows.
+fn relative_path_from(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 && path.has_root() {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for comp in &path_components[common..] {
+        relative.push(comp.as_os_str());
+    }
+    Some(relative)
+}
+
+/// Finds the longest run of leading directories shared by every path.
+fn common_dir_prefix<'a>(paths: impl IntoIterator<Item = &'a Path>) -> PathBuf {
+    let mut prefix: Option<Vec<Component>> = None;
+    for path in paths {
+        let dirs: Vec<Component> = path
+            .parent()
+            .map_or(Vec::new(), |p| p.components().collect());
+        prefix = Some(match prefix {
+            None => dirs,
+            Some(current) => current
+                .into_iter()
+                .zip(dirs)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    prefix.unwrap_or_default().iter().collect()
+}
+
+/// Returns where `path` really points if it was reached through a symlink.
+///
+/// A file that is itself a link reports the link's own target; a regular file
+/// inside a linked directory reports its canonical location relative to the
+/// canonical `root`.
+fn symlink_target(path: &Path, relative_path: &Path, root: &Path) -> Option<PathBuf> {
+    if path.symlink_metadata().ok()?.file_type().is_symlink() {
+        return fs::read_link(path).ok();
+    }
+
+    let canonical_root = normalize_path(&fs::canonicalize(root).ok()?);
+    let canonical = normalize_path(&fs::canonicalize(path).ok()?);
+    if canonical == canonical_root.join(relative_path) {
+        return None;
+    }
+    relative_path_from(&canonical, &canonical_root).or(Some(canonical))
+}
+
+/// Formats a byte count for display, e.g. `512 B`, `4.2 KB`, `1.1 MB`.
+fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// [`human_bytes`], or the exact count with `--raw-bytes`.
+fn format_size(n: usize, raw: bool) -> String {
+    if raw {
+        format!("{} bytes", n)
+    } else {
+        human_bytes(n as u64)
+    }
+}
+
+fn log_skip(path: &Path, reason: &str) {
+    if log::explaining() {
+        explain!(path.display(), "excluded ({})", reason);
+    } else {
+        verbose!("- skipped {} ({})", path.display(), reason);
+    }
+}
+
+/// Logs a skip that is worth a notice even without `--verbose`.
+fn log_noted_skip(path: &Path, reason: &str) {
+    if log::explaining() {
+        log_skip(path, reason);
+    } else {
+        notice!("Skipping {:?}: {}", path, reason);
+    }
+}
+
+fn log_binary_skip(path: &Path) {
+    if log::enabled(Verbosity::Verbose) || log::explaining() {
+        log_skip(path, "binary");
+    } else {
+        notice!("Skipping binary file {:?}", path);
+    }
+}
+
+fn is_readme(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .is_some_and(|name| name.to_lowercase().starts_with("readme"))
+}
+
+fn file_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Reads the submodule paths (relative to `root`) declared in `root/.gitmodules`.
+fn read_gitmodules(root: &Path) -> HashSet<PathBuf> {
+    let Ok(text) = fs::read_to_string(root.join(".gitmodules")) else {
+        return HashSet::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "path").then(|| PathBuf::from(value.trim()))
+        })
+        .collect()
+}
+
+/// Whether `dir` is a git submodule: declared in `.gitmodules`, or checked out
+/// with a `.git` file (rather than directory) pointing at the parent's git dir.
+fn is_submodule(dir: &Path, relative_dir: &Path, declared: &HashSet<PathBuf>) -> bool {
+    declared.contains(relative_dir) || dir.join(".git").is_file()
+}
+
+/// Counts the entries under `directory` that the filtered walk never yielded.
+///
+/// The ignore crate doesn't report what it skips, so this re-walks the tree
+/// without any filters and counts each entry missing from `walked`. Skipped
+/// directories are counted once and not descended into.
+fn count_ignored(
+    directory: &str,
+    walked: &HashSet<PathBuf>,
+    follow_links: bool,
+    max_open: usize,
+    max_depth: Option<usize>,
+) -> usize {
+    let mut ignored = 0;
+    let unfiltered = WalkDir::new(directory)
+        .follow_links(follow_links)
+        .max_open(max_open)
+        .max_depth(max_depth.unwrap_or(usize::MAX))
+        .into_iter()
+        .filter_entry(|entry| {
+            if walked.contains(entry.path()) {
+                true
+            } else {
+                ignored += 1;
+                false
+            }
+        });
+    // Errors here were already surfaced by the filtered walk.
+    for _ in unfiltered {}
+    ignored
+}
+
+/// Whether `a` and `b` name the same directory, comparing them as given when
+/// either can't be resolved.
+fn same_directory(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => normalize_path(a) == normalize_path(b),
+    }
+}
+
+/// Hex digits of the SHA-256 shown in headers; templates get the full hash.
+const SHORT_HASH_LEN: usize = 12;
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Reports how `files` differ from the manifest at `path`, if there is one,
+/// then overwrites it with theirs. The manifest has one `sha256  path` line
+/// per file, as `sha256sum` writes them, with the hashes of the files as
+/// read from `read_hashes`.
+fn update_snapshot(
+    path: &Path,
+    files: &[CollectedFile],
+    read_hashes: &HashMap<PathBuf, String>,
+) -> io::Result<()> {
+    let current: BTreeMap<String, String> = files
+        .iter()
+        .map(|file| {
+            (
+                file.relative_path.to_string_lossy().into_owned(),
+                read_hashes[&file.relative_path].clone(),
+            )
+        })
+        .collect();
+
+    match fs::read_to_string(path) {
+        Ok(text) => {
+            let previous: BTreeMap<&str, &str> = text
+                .lines()
+                .filter_map(|line| line.split_once("  "))
+                .map(|(hash, path)| (path, hash))
+                .collect();
+            let mut changes = Vec::new();
+            for (file, hash) in &current {
+                match previous.get(file.as_str()) {
+                    None => changes.push(format!("+ {}", file)),
+                    Some(old) if old != hash => changes.push(format!("~ {}", file)),
+                    Some(_) => {}
+                }
+            }
+            for file in previous.keys() {
+                if !current.contains_key(*file) {
+                    changes.push(format!("- {}", file));
+                }
+            }
+            let count = |mark: &str| changes.iter().filter(|c| c.starts_with(mark)).count();
+            notice!(
+                "Since the last snapshot: {} added, {} removed, {} changed.",
+                count("+"),
+                count("-"),
+                count("~")
+            );
+            for change in &changes {
+                notice!("    {}", change);
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            notice!("Writing a new snapshot to {}.", path.display());
+        }
+        Err(e) => return Err(e),
+    }
+
+    let manifest: String = current
+        .iter()
+        .map(|(file, hash)| format!("{}  {}\n", hash, file))
+        .collect();
+    fs::write(path, manifest)
+}
+
+/// Prints the `--manifest-only` list of `files`, hashed as collected. They
+/// are sorted by path whatever the buffer order, so manifests compare line
+/// by line.
+fn print_manifest(files: &[CollectedFile], format: ManifestFormat) -> io::Result<()> {
+    let path = |file: &CollectedFile| file.relative_path.to_string_lossy().replace('\\', "/");
+    let mut files: Vec<&CollectedFile> = files.iter().collect();
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    let mut stdout = io::stdout().lock();
+    match format {
+        ManifestFormat::Tsv => {
+            for file in files {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}",
+                    path(file),
+                    sha256_hex(file.content.as_bytes()),
+                    file.content.len()
+                )?;
+            }
+        }
+        ManifestFormat::Json => {
+            let manifest: Vec<serde_json::Value> = files
+                .iter()
+                .map(|file| {
+                    serde_json::json!({
+                        "path": path(file),
+                        "sha256": sha256_hex(file.content.as_bytes()),
+                        "bytes": file.content.len(),
+                    })
+                })
+                .collect();
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&manifest)?)?;
+        }
+    }
+    stdout.flush()
+}
+
+/// Entries kept in a `--size-history` file, over all sources.
+const SIZE_HISTORY_LEN: usize = 100;
+
+/// Appends this run's buffer size to the `--size-history` file, one
+/// `time<TAB>source<TAB>bytes<TAB>tokens` line per run, and returns the
+/// previous size recorded for `source`, if any. Tokens are estimated from
+/// the bytes as `--stats-json` does.
+fn update_size_history(path: &Path, source: &str, bytes: usize) -> io::Result<Option<(u64, u64)>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut entries: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    let previous = entries.iter().rev().find_map(|line| {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            [_, entry_source, bytes, tokens] if entry_source == source => {
+                Some((bytes.parse().ok()?, tokens.parse().ok()?))
+            }
+            _ => None,
+        }
+    });
+    let entry = format!(
+        "{}\t{}\t{}\t{}",
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        source,
+        bytes,
+        estimate_tokens_from_bytes(bytes as u64)
+    );
+    entries.push(&entry);
+    let kept = &entries[entries.len().saturating_sub(SIZE_HISTORY_LEN)..];
+    fs::write(path, kept.join("\n") + "\n")?;
+    Ok(previous)
+}
+
+/// A signed change, e.g. `+12.0 KB` or `-300`, with `show` formatting its size.
+fn format_delta(previous: u64, current: u64, show: impl Fn(u64) -> String) -> String {
+    let sign = if current < previous { '-' } else { '+' };
+    format!("{}{}", sign, show(current.abs_diff(previous)))
+}
+
+/// Pipes `content` through `command` (run by the platform shell) and returns its stdout.
+///
+/// For per-file filters, the file's relative path is passed in
+/// `CODE_COLLECTOR_PATH` so the command can, e.g., pick a formatter by extension.
+fn run_shell_filter(command: &str, path: Option<&Path>, content: &str) -> Result<String, String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    if let Some(path) = path {
+        shell.env("CODE_COLLECTOR_PATH", path);
+    }
+    let mut child = shell
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    // Write from another thread so a command that streams its output can't
+    // deadlock against us filling its stdin.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = content.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    // A command that exits without reading all of its input is fine.
+    let _ = writer.join();
+
+    if !output.status.success() {
+        // Shells exit with 127 when they can't find the command.
+        if !cfg!(windows) && output.status.code() == Some(127) {
+            return Err(format!("command not found ({})", output.status));
+        }
+        return Err(output.status.to_string());
+    }
+    String::from_utf8(output.stdout).map_err(|_| "output is not valid UTF-8".to_string())
+}
+
+/// Names the line endings of `content` if they aren't all `\n`: `CRLF`, or
+/// `mixed` when both kinds occur.
+fn non_lf_line_endings(content: &str) -> Option<&'static str> {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count() - crlf;
+    match (crlf, lf) {
+        (0, _) => None,
+        (_, 0) => Some("CRLF"),
+        _ => Some("mixed"),
+    }
+}
+
+/// Names the encoding of `bytes` from a byte order mark, or from whether
+/// they are valid UTF-8. Encodings without a BOM other than UTF-8 aren't told apart.
+fn detect_encoding(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        "UTF-8 with BOM"
+    } else if bytes.starts_with(&[0xFF, 0xFE, 0, 0]) {
+        "UTF-32LE"
+    } else if bytes.starts_with(&[0, 0, 0xFE, 0xFF]) {
+        "UTF-32BE"
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        "UTF-16LE"
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        "UTF-16BE"
+    } else if bytes.is_ascii() {
+        "ASCII"
+    } else if std::str::from_utf8(bytes).is_ok() {
+        "UTF-8"
+    } else {
+        "not UTF-8"
+    }
+}
+
+/// Prints the files left after every filter that doesn't need their content,
+/// as `--print0` or `--dry-run` (with `--json` or `--stats-json`) asks, with
+/// their sizes on disk from `sizes`.
+fn print_listing(
+    args: &Cli,
+    files: &[CollectedFile],
+    sizes: &HashMap<PathBuf, u64>,
+) -> serde_json::Result<()> {
+    if args.print0 {
+        for file in files {
+            print!("{}\0", file.relative_path.to_string_lossy());
+        }
+    } else if args.stats_json {
+        let mut totals = ExtensionTotals::new();
+        for file in files {
+            let (count, bytes) = totals.entry(file.extension.clone()).or_default();
+            *count += 1;
+            *bytes += sizes[&file.relative_path];
+        }
+        println!("{}", serde_json::to_string_pretty(&size_stats(&totals))?);
+    } else if args.json {
+        let entries: Vec<serde_json::Value> = files
+            .iter()
+            .map(|file| {
+                let modified = file.modified.filter(|_| !args.deterministic);
+                dry_run_entry(
+                    &file.relative_path,
+                    sizes[&file.relative_path],
+                    modified,
+                    &file.extension,
+                )
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for file in files {
+            println!("{}", file.relative_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// One element of the `--dry-run --json` array, for a file that passed every
+/// filter that doesn't need its content.
+fn dry_run_entry(
+    path: &Path,
+    bytes: u64,
+    modified: Option<SystemTime>,
+    extension: &str,
+) -> serde_json::Value {
+    serde_json::json!({
+        "path": path.to_string_lossy(),
+        "bytes": bytes,
+        "mtime": modified.map(|m| humantime::format_rfc3339_seconds(m).to_string()),
+        "extension": extension,
+        "would_include": true,
+    })
+}
+
+/// Removes a single leading UTF-8 byte order mark, as written by some Windows editors.
+fn strip_bom(content: String) -> String {
+    match content.strip_prefix('\u{FEFF}') {
+        Some(stripped) => stripped.to_string(),
+        None => content,
+    }
+}
+
+/// Average line length above which a file is considered minified.
+const MINIFIED_AVG_LINE_LENGTH: usize = 500;
+
+/// Share of the file a single line must take up to be considered minified.
+const MINIFIED_DOMINANT_LINE_SHARE: f64 = 0.8;
+
+/// Files smaller than this are never considered minified by the dominant-line rule.
+const MINIFIED_MIN_BYTES: usize = 1024;
+
+fn looks_minified(content: &str) -> bool {
+    let line_lengths: Vec<usize> = content.lines().map(str::len).collect();
+    if line_lengths.is_empty() {
+        return false;
+    }
+    let total: usize = line_lengths.iter().sum();
+    let longest = line_lengths.iter().copied().max().unwrap_or(0);
+
+    total / line_lengths.len() > MINIFIED_AVG_LINE_LENGTH
+        || (content.len() >= MINIFIED_MIN_BYTES
+            && longest as f64 >= content.len() as f64 * MINIFIED_DOMINANT_LINE_SHARE)
+}
+
+/// Re-serializes a JSON document without insignificant whitespace, keeping key order.
+fn minify_json(content: &str) -> Result<String, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    serde_json::to_string(&value)
+}
+
+/// Words that mark a leading comment block as a license header.
+const LICENSE_KEYWORDS: &[&str] = &["copyright", "license", "spdx-license-identifier"];
+
+/// Removes the comment block at the top of `content` (after any shebang line)
+/// if it mentions a copyright or license, along with the blank lines after it.
+///
+/// Only whole-line comments in the file's own syntax count, and doc comments
+/// (`///`, `//!`) end the block, so code and module docs are never touched.
+fn strip_leading_license(content: &str, style: &CommentStyle) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let start = usize::from(lines.first().is_some_and(|line| line.starts_with("#!")));
+
+    let is_doc = |line: &str| line.starts_with("///") || line.starts_with("//!");
+    // `//` languages may also open with a C-style `/* ... */` header.
+    let block = match style {
+        CommentStyle::Block(open, close) => Some((*open, *close)),
+        CommentStyle::Line("//") => Some(("/*", "*/")),
+        CommentStyle::Line(_) => None,
+    };
+    let opens_block = |(open, _): &(&str, &str)| {
+        lines
+            .get(start)
+            .is_some_and(|line| line.trim_start().starts_with(open))
+    };
+
+    let mut end = start;
+    if let Some((_, close)) = block.filter(opens_block) {
+        match lines[start..].iter().position(|line| line.contains(close)) {
+            Some(offset) => end = start + offset + 1,
+            None => return content.to_string(),
+        }
+    } else if let CommentStyle::Line(prefix) = style {
+        while end < lines.len() {
+            let line = lines[end].trim_start();
+            if !line.starts_with(prefix) || is_doc(line) {
+                break;
+            }
+            end += 1;
+        }
+    }
+
+    let header = lines[start..end].join("\n").to_lowercase();
+    if end == start
+        || !LICENSE_KEYWORDS
+            .iter()
+            .any(|keyword| header.contains(keyword))
+    {
+        return content.to_string();
+    }
+
+    while end < lines.len() && lines[end].trim().is_empty() {
+        end += 1;
+    }
+    let mut stripped: Vec<&str> = lines[..start].to_vec();
+    stripped.extend(&lines[end..]);
+    let mut out = stripped.join("\n");
+    if content.ends_with('\n') && !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Keeps only the comment lines of `content`, judged line by line from the
+/// comment syntax of `extension` (plus `/* */` blocks in `//` languages and
+/// docstrings in Python).
+///
+/// This is a heuristic: comments after code on the same line are dropped, and
+/// comment markers inside string literals are taken at face value.
+fn extract_comments(content: &str, extension: &str) -> String {
+    let (line_prefix, block) = match get_comment_syntax(extension) {
+        CommentStyle::Line(prefix) if prefix == "//" => (Some(prefix), Some(("/*", "*/"))),
+        CommentStyle::Line(prefix) => (Some(prefix), None),
+        CommentStyle::Block(start, end) => (None, Some((start, end))),
+    };
+    let docstring = extension == "py";
+
+    let mut kept = Vec::new();
+    // The delimiter closing the block comment or docstring we're inside, if any.
+    let mut open: Option<&str> = None;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if let Some(end) = open {
+            kept.push(line);
+            if trimmed.contains(end) {
+                open = None;
+            }
+        } else if line_prefix.is_some_and(|prefix| trimmed.starts_with(prefix)) {
+            kept.push(line);
+        } else if let Some((start, end)) = block.filter(|(start, _)| trimmed.starts_with(start)) {
+            kept.push(line);
+            if !trimmed[start.len()..].contains(end) {
+                open = Some(end);
+            }
+        } else if let Some(quote) = ["\"\"\"", "'''"]
+            .into_iter()
+            .find(|quote| docstring && trimmed.starts_with(quote))
+        {
+            kept.push(line);
+            if !trimmed[quote.len()..].contains(quote) {
+                open = Some(quote);
+            }
+        }
+    }
+
+    let mut comments = kept.join("\n");
+    if !comments.is_empty() {
+        comments.push('\n');
+    }
+    comments
+}
+
+/// Keeps only the documentation comments of `content`: `///`, `//!`, `/** */`
+/// and `/*! */` in Rust, docstrings in Python, and `/** */` blocks in other
+/// C-family languages. Other languages have none.
+///
+/// Like [`extract_comments`], this goes line by line, so a doc comment must
+/// start its line.
+fn extract_docstrings(content: &str, extension: &str) -> String {
+    let (line_prefixes, block_starts, quotes): (&[&str], &[&str], &[&str]) = match extension {
+        "rs" => (&["///", "//!"], &["/**", "/*!"], &[]),
+        "py" => (&[], &[], &["\"\"\"", "'''"]),
+        "js" | "ts" | "jsx" | "tsx" | "java" | "kt" | "swift" | "php" | "c" | "h" | "cpp"
+        | "hpp" | "cs" => (&[], &["/**"], &[]),
+        _ => return String::new(),
+    };
+
+    let mut kept = Vec::new();
+    // The delimiter closing the doc block or docstring we're inside, if any.
+    let mut open: Option<&str> = None;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if let Some(end) = open {
+            kept.push(line);
+            if trimmed.contains(end) {
+                open = None;
+            }
+        } else if line_prefixes
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix) && !trimmed.starts_with("////"))
+        {
+            kept.push(line);
+        } else if let Some(start) = block_starts
+            .iter()
+            .find(|start| trimmed.starts_with(*start) && !trimmed.starts_with("/**/"))
+        {
+            kept.push(line);
+            if !trimmed[start.len()..].contains("*/") {
+                open = Some("*/");
+            }
+        } else if let Some(quote) = quotes.iter().find(|quote| trimmed.starts_with(*quote)) {
+            kept.push(line);
+            if !trimmed[quote.len()..].contains(quote) {
+                open = Some(quote);
+            }
+        }
+    }
+
+    let mut docs = kept.join("\n");
+    if !docs.is_empty() {
+        docs.push('\n');
+    }
+    docs
+}
+
+/// Keeps only the lines matching `regex` plus `n` lines of context on either side.
+///
+/// Overlapping or touching windows are merged, and a `...` line marks each gap
+/// between the remaining regions.
+fn extract_context(content: &str, regex: &Regex, n: usize) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        let start = i.saturating_sub(n);
+        let end = (i + n).min(lines.len() - 1);
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end + 1 => *last_end = end.max(*last_end),
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    for (i, (start, end)) in ranges.iter().enumerate() {
+        if i > 0 {
+            out.push_str("...\n");
+        }
+        for line in &lines[*start..=*end] {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Parses a rename map: one `from=to` pair per line, ignoring blank lines and `#` comments.
+fn parse_rename_map(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut renames = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((from, to)) if !from.is_empty() => {
+                renames.push((from.to_string(), to.to_string()));
+            }
+            _ => {
+                return Err(format!(
+                    "line {}: expected `from=to`, got {:?}",
+                    i + 1,
+                    line
+                ))
+            }
+        }
+    }
+    Ok(renames)
+}
+
+/// Applies each rename in order, adding the number of replacements made to `count`.
+fn apply_renames(text: &str, renames: &[(String, String)], count: &mut usize) -> String {
+    let mut text = text.to_string();
+    for (from, to) in renames {
+        let matches = text.matches(from.as_str()).count();
+        if matches > 0 {
+            *count += matches;
+            text = text.replace(from.as_str(), to);
+        }
+    }
+    text
+}
+
+/// Parses a list of relative paths, one per line, ignoring blank lines and `#` comments.
+fn parse_path_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let path = Path::new(line);
+            path.strip_prefix(".").unwrap_or(path).to_owned()
+        })
+        .collect()
+}
+
+/// The lines that `--with-summary-header` puts at the top of the buffer, with
+/// the current date when `dated` is set.
+fn summary_header(source: &str, files: &[CollectedFile], dated: bool) -> String {
+    let total_lines: usize = files.iter().map(|f| f.content.lines().count()).sum();
+    let mut extensions: Vec<&str> = files
+        .iter()
+        .map(|f| f.extension.as_str())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+
+    let date = if dated {
+        format!(
+            "Date: {}\n",
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        "Collected from: {}\n{}Files: {}\nLines: {}\nExtensions: {}\n",
+        source,
+        date,
+        files.len(),
+        total_lines,
+        extensions.join(", ")
+    )
+}
+
+/// Runs `git` in `dir` and returns its trimmed stdout, or `None` if it fails or prints nothing.
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = stdout.trim();
+    (output.status.success() && !stdout.is_empty()).then(|| stdout.to_string())
+}
+
+/// Runs `git -C dir diff` with `diff_args`, returning stdout or git's error message.
+fn git_diff(dir: &Path, diff_args: &[String]) -> Result<String, String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("diff")
+        .args(diff_args)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `--git-diff`: puts the unified diff of `range` (or of the working tree
+/// against the index) into the buffer instead of whole files.
+///
+/// The -e, -x and --exclude-dir-glob filters become git pathspecs; other
+/// filters don't apply.
+fn run_git_diff(
+    args: &Cli,
+    directory: &str,
+    range: Option<&str>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let dir = Path::new(if directory.is_empty() { "." } else { directory });
+    info!("Diffing directory: {}", dir.display());
+    let mut timings = Timings::new();
+
+    let magic = if args.ignore_case {
+        "glob,icase"
+    } else {
+        "glob"
+    };
+    let mut pathspecs: Vec<String> = selected_extensions(args)
+        .iter()
+        .map(|ext| format!(":({})**/*.{}", magic, ext))
+        .collect();
+    if pathspecs.is_empty() {
+        pathspecs.push(".".to_string());
+    }
+    for excluded in DEFAULT_EXCLUDED_DIRS
+        .iter()
+        .copied()
+        .chain(args.exclude_dirs.iter().map(String::as_str))
+    {
+        pathspecs.push(format!(":(exclude,{})**/{}/**", magic, excluded));
+    }
+    for pattern in &args.exclude_dir_globs {
+        pathspecs.push(format!(
+            ":(exclude,{}){}/**",
+            magic,
+            pattern.trim_matches('/')
+        ));
+    }
+
+    let mut diff_args = vec!["--relative".to_string()];
+    diff_args.extend(range.map(str::to_string));
+    let mut names_args = diff_args.clone();
+    names_args.push("--name-only".to_string());
+    diff_args.push("--".to_string());
+    names_args.push("--".to_string());
+    diff_args.extend(pathspecs.iter().cloned());
+    names_args.extend(pathspecs);
+
+    let names = git_diff(dir, &names_args)?;
+    let changed: Vec<&str> = names.lines().filter(|line| !line.is_empty()).collect();
+    let diff = git_diff(dir, &diff_args)?;
+    timings.lap("git diff");
+
+    let mut root = TreeNode::new(String::new());
+    for path in &changed {
+        let components: Vec<String> = path.split('/').map(str::to_string).collect();
+        root.add_path(&components);
+    }
+    if let Some(path) = &args.tree_json {
+        write_tree_json(path, &root, args.tree_sort)?;
+    }
+    let mut tree_options = TreeOptions {
+        file_counts: args.tree_file_counts,
+        style: args.tree_style,
+        sort: args.tree_sort,
+        max_entries: None,
+        max_depth: args.tree_depth.map(NonZeroUsize::get),
+        colored: false,
+        max_width: None,
+    };
+    let tree = root.render(&tree_options);
+    tree_options.colored = true;
+    tree_options.max_entries = args.max_tree_entries.map(NonZeroUsize::get);
+    tree_options.max_width = args.tree_width.map(NonZeroUsize::get);
+    print_tree(&root.render(&tree_options));
+
+    let command = match range {
+        Some(range) => format!("git diff {}", range),
+        None => "git diff".to_string(),
+    };
+    let mut buffer = format!(
+        "// Unified diff ({}) of {} changed files, not their full contents\n\n{}",
+        command,
+        changed.len(),
+        diff
+    );
+    if let Some(tag) = &args.wrap_in_tag {
+        wrap_in_tag(tag, &mut buffer);
+    }
+    // Every format gets the same diff.
+    let render = |_: OutputFormat| post_process(args, buffer.clone());
+    summary!("Collected the diff of {} files.", changed.len());
+    deliver(args, &render, None, &tree, &mut timings)?;
+    Ok(changed.len())
+}
+
+/// Reads a newline-separated list of paths relative to the collected directory
+/// from `source`, or from stdin when it is `-`. Blank lines are skipped.
+fn read_file_list(source: &str) -> io::Result<HashSet<PathBuf>> {
+    let list = if source == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(source)?
+    };
+    Ok(list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| PathBuf::from(line.strip_prefix("./").unwrap_or(line)))
+        .collect())
+}
+
+/// One entry of an `--input-json` file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputEntry {
+    path: PathBuf,
+    /// 1-based inclusive `[start, end]` pairs; the whole file if there are none.
+    #[serde(default)]
+    ranges: Vec<(usize, usize)>,
+}
+
+/// What an `--input-json` file asks for: the paths in the order listed,
+/// without repeats, and the line ranges of those given any.
+struct InputSpec {
+    paths: Vec<PathBuf>,
+    ranges: Vec<LineRange>,
+}
+
+fn read_input_spec(source: &str) -> Result<InputSpec, String> {
+    let text = fs::read_to_string(source)
+        .map_err(|e| format!("could not read --input-json {}: {}", source, e))?;
+    let entries: Vec<InputEntry> = serde_json::from_str(&text)
+        .map_err(|e| format!("invalid --input-json {}: {}", source, e))?;
+    let mut spec = InputSpec {
+        paths: Vec::new(),
+        ranges: Vec::new(),
+    };
+    for entry in entries {
+        let path = entry
+            .path
+            .strip_prefix(".")
+            .unwrap_or(&entry.path)
+            .to_owned();
+        // Joined onto the collected directory, so an absolute or `..` path would leave it.
+        let inside = path
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_) | Component::CurDir));
+        if !inside || path.as_os_str().is_empty() {
+            return Err(format!(
+                "invalid --input-json {}: {:?} isn't a path inside the collected directory",
+                source, entry.path
+            ));
+        }
+        for (start, end) in entry.ranges {
+            if start == 0 || end < start {
+                return Err(format!(
+                    "invalid --input-json {}: range [{}, {}] of {:?}: lines start at 1 and the end can't precede the start",
+                    source, start, end, path
+                ));
+            }
+            spec.ranges.push(LineRange {
+                path: path.clone(),
+                start,
+                end,
+            });
+        }
+        if !spec.paths.contains(&path) {
+            spec.paths.push(path);
+        }
+    }
+    Ok(spec)
+}
+
+/// The files git tracks below `dir`, relative to `dir`.
+fn git_tracked_files(dir: &Path) -> Result<HashSet<PathBuf>, String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["ls-files", "-z"])
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("--tracked-only needs git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "--tracked-only: {} is not inside a git repository",
+            dir.display()
+        ));
+    }
+    Ok(output
+        .stdout
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect())
+}
+
+/// Lines naming the remote, branch and commit of the repository containing
+/// `dir`, or `None` if it isn't inside a git work tree.
+///
+/// The remote and branch lines are left out when there is no `origin` or HEAD is detached.
+fn git_info(dir: &Path) -> Option<String> {
+    let commit = git_output(dir, &["rev-parse", "HEAD"])?;
+    let mut info = String::new();
+    if let Some(remote) = git_output(dir, &["remote", "get-url", "origin"]) {
+        info.push_str(&format!("Repository: {}\n", remote));
+    }
+    if let Some(branch) = git_output(dir, &["branch", "--show-current"]) {
+        info.push_str(&format!("Branch: {}\n", branch));
+    }
+    info.push_str(&format!("Commit: {}\n", commit));
+    Some(info)
+}
+
+/// Single files where editors and assistants look for project instructions.
+const AI_RULE_FILES: &[&str] = &[".cursorrules", ".github/copilot-instructions.md"];
+
+/// Directory whose files (recursively) are Cursor project rules.
+const AI_RULE_DIR: &str = ".cursor/rules";
+
+/// The project's AI instruction files under `dir`, each as a summary block
+/// labeled with its path, or `None` if there are none.
+fn ai_rules(dir: &Path) -> Option<Vec<SummaryBlock>> {
+    let mut paths: Vec<PathBuf> = AI_RULE_FILES.iter().map(|name| dir.join(name)).collect();
+    let mut rules: Vec<PathBuf> = WalkDir::new(dir.join(AI_RULE_DIR))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect();
+    rules.sort();
+    paths.extend(rules);
+
+    let mut blocks = Vec::new();
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                notice!("Could not read AI rules file {:?}: {}", path, e);
+                continue;
+            }
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        blocks.push(SummaryBlock::File(
+            format!("AI rules: {}", relative.to_string_lossy()),
+            content,
+        ));
+    }
+    (!blocks.is_empty()).then_some(blocks)
+}
+
+/// One block of the summary that opens the buffer, ahead of the files.
+enum SummaryBlock {
+    /// Lines of text under an optional heading, e.g. the `--toc` entries
+    /// under `Contents:`.
+    Lines(Option<&'static str>, String),
+    /// A file given whole under a label, e.g. an AI rules file.
+    File(String, String),
+}
+
+/// Lays out the summary blocks in `format`: as `//` comments, as fenced
+/// blocks in Markdown, or as plain lines in the repomix layout. Each block
+/// ends with a blank line. XML and JSON carry the summary inside the
+/// document, so for them this is empty.
+fn render_summary(blocks: &[SummaryBlock], format: OutputFormat) -> String {
+    let mut out = String::new();
+    for block in blocks {
+        match (block, format) {
+            (_, OutputFormat::Xml | OutputFormat::Json) => return out,
+            (SummaryBlock::Lines(heading, lines), _) => {
+                let prefix = if format == OutputFormat::Comments {
+                    "// "
+                } else {
+                    ""
+                };
+                if format == OutputFormat::Markdown {
+                    out.push_str("```\n");
+                }
+                for line in heading.iter().copied().chain(lines.lines()) {
+                    out.push_str(&format!("{}{}\n", prefix, line));
+                }
+                if format == OutputFormat::Markdown {
+                    out.push_str("```\n");
+                }
+            }
+            (SummaryBlock::File(label, content), OutputFormat::Markdown) => {
+                let fence = markdown_fence(content);
+                out.push_str(&format!(
+                    "## {}\n\n{}\n{}\n{}\n",
+                    label,
+                    fence,
+                    content.trim_end(),
+                    fence
+                ));
+            }
+            (SummaryBlock::File(label, content), _) => {
+                if format == OutputFormat::Comments {
+                    out.push_str("// ");
+                }
+                out.push_str(&format!("{}\n{}", label, content));
+                if !content.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Puts `buffer` between `<tag>` and `</tag>` lines.
+fn wrap_in_tag(tag: &str, buffer: &mut String) {
+    if !buffer.is_empty() && !buffer.ends_with('\n') {
+        buffer.push('\n');
+    }
+    buffer.insert_str(0, &format!("<{}>\n", tag));
+    buffer.push_str(&format!("</{}>\n", tag));
+}
+
+/// The text of a file's first bytes, without a character cut in two at the
+/// end; `None` if they aren't UTF-8 otherwise.
+fn utf8_prefix(mut bytes: Vec<u8>) -> Option<String> {
+    if let Err(e) = std::str::from_utf8(&bytes) {
+        if e.error_len().is_some() {
+            return None;
+        }
+        bytes.truncate(e.valid_up_to());
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Cuts the blank lines that follow the last file, leaving one final newline.
+fn trim_trailing_newlines(buffer: &mut String) {
+    let trimmed = buffer.trim_end_matches(['\n', '\r']).len();
+    if trimmed > 0 {
+        buffer.truncate(trimmed);
+        buffer.push('\n');
+    }
+}
+
+/// A rough token count for `text`: about four characters per token, which is
+/// close enough for English text and code under common tokenizers.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Like [`estimate_tokens`], but from a byte count, for when the text itself
+/// isn't at hand: four bytes per token, which is the same for ASCII.
+fn estimate_tokens_from_bytes(bytes: u64) -> u64 {
+    bytes.div_ceil(4)
+}
+
+/// Files and bytes per extension.
+type ExtensionTotals = BTreeMap<String, (u64, u64)>;
+
+fn extension_totals(files: &[CollectedFile]) -> ExtensionTotals {
+    let mut totals = ExtensionTotals::new();
+    for file in files {
+        let (count, bytes) = totals.entry(file.extension.clone()).or_default();
+        *count += 1;
+        *bytes += file.content.len() as u64;
+    }
+    totals
+}
+
+/// The `--summary-table` report, largest extension first, with a total row.
+fn extension_table(by_extension: &ExtensionTotals, raw_bytes: bool) -> Vec<String> {
+    let total_files: u64 = by_extension.values().map(|(files, _)| files).sum();
+    let total_bytes: u64 = by_extension.values().map(|(_, bytes)| bytes).sum();
+    let mut rows: Vec<(String, u64, u64)> = by_extension
+        .iter()
+        .map(|(extension, &(files, bytes))| {
+            let name = if extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", extension)
+            };
+            (name, files, bytes)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    rows.push(("Total".to_string(), total_files, total_bytes));
+
+    let cells: Vec<[String; 4]> = rows
+        .into_iter()
+        .map(|(name, files, bytes)| {
+            let share = if total_bytes == 0 {
+                0.0
+            } else {
+                bytes as f64 * 100.0 / total_bytes as f64
+            };
+            [
+                name,
+                files.to_string(),
+                format_size(bytes as usize, raw_bytes),
+                format!("{:.1}%", share),
+            ]
+        })
+        .collect();
+    let header = ["Extension", "Files", "Size", "Share"].map(String::from);
+    let widths: Vec<usize> = (0..4)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&cells)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    // Names on the left, numbers right-aligned.
+    let line = |row: &[String; 4]| {
+        format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        )
+    };
+    std::iter::once(&header).chain(&cells).map(line).collect()
+}
+
+/// The `--stats-json` report: totals, then per extension, of file counts,
+/// bytes, and tokens estimated from the bytes by [`estimate_tokens_from_bytes`].
+fn size_stats(by_extension: &ExtensionTotals) -> serde_json::Value {
+    let entry = |files: u64, bytes: u64| {
+        serde_json::json!({
+            "files": files,
+            "bytes": bytes,
+            "estimated_tokens": estimate_tokens_from_bytes(bytes),
+        })
+    };
+    let files = by_extension.values().map(|(files, _)| files).sum();
+    let bytes = by_extension.values().map(|(_, bytes)| bytes).sum();
+    let mut stats = entry(files, bytes);
+    stats["by_extension"] = by_extension
+        .iter()
+        .map(|(extension, &(files, bytes))| (extension.clone(), entry(files, bytes)))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+    stats
+}
+
+/// The size limit of one part when splitting into `--output-dir`.
+#[derive(Clone, Copy)]
+enum PartLimit {
+    Bytes(usize),
+    Tokens(usize),
+}
+
+impl PartLimit {
+    fn max(self) -> usize {
+        match self {
+            PartLimit::Bytes(max) | PartLimit::Tokens(max) => max,
+        }
+    }
+
+    fn measure(self, text: &str) -> usize {
+        match self {
+            PartLimit::Bytes(_) => text.len(),
+            PartLimit::Tokens(_) => count_tokens(text),
+        }
+    }
+
+    fn describe(self, n: usize, raw_bytes: bool) -> String {
+        match self {
+            PartLimit::Bytes(_) => format_size(n, raw_bytes),
+            PartLimit::Tokens(_) => describe_tokens(n),
+        }
+    }
+}
+
+/// Groups formatted files into parts within `limit`, never splitting a file.
+/// Also returns, for each file, the index of the part it went into.
+///
+/// `head` (the title and summary blocks) opens the first part and counts
+/// against its limit like a file would. A file that alone exceeds the limit
+/// gets a part of its own.
+fn split_into_parts(
+    files: &[CollectedFile],
+    formatted: &[String],
+    head: &str,
+    limit: PartLimit,
+    raw_bytes: bool,
+) -> (Vec<String>, Vec<usize>) {
+    let mut parts: Vec<String> = Vec::new();
+    let mut placement = Vec::with_capacity(files.len());
+    let mut current = head.to_string();
+    let mut current_size = limit.measure(head);
+
+    for (file, formatted) in files.iter().zip(formatted) {
+        let size = limit.measure(formatted);
+        if size > limit.max() {
+            eprintln!(
+                "File {:?} is {}, more than the {} part size; writing it to its own part",
+                file.relative_path,
+                limit.describe(size, raw_bytes),
+                limit.describe(limit.max(), raw_bytes)
+            );
+        }
+        if !current.is_empty() && current_size + size > limit.max() {
+            parts.push(std::mem::take(&mut current));
+            current_size = 0;
+        }
+        current.push_str(formatted);
+        current_size += size;
+        placement.push(parts.len());
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    (parts, placement)
+}
+
+/// The file name of the part at `index` in `--output-dir`.
+fn part_file_name(index: usize) -> String {
+    format!("part_{:03}.txt", index + 1)
+}
+
+/// A human-readable name for the files with `extension`, used in group banners.
+fn language_name(extension: &str) -> String {
+    let name = match extension {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" => "JavaScript",
+        "ts" => "TypeScript",
+        "c" | "h" => "C",
+        "cpp" | "hpp" => "C++",
+        "java" => "Java",
+        "cs" => "C#",
+        "go" => "Go",
+        "swift" => "Swift",
+        "kt" | "kts" => "Kotlin",
+        "sh" => "Shell",
+        "rb" => "Ruby",
+        "pl" => "Perl",
+        "r" => "R",
+        "php" => "PHP",
+        "ps1" => "PowerShell",
+        "html" | "xhtml" => "HTML",
+        "css" => "CSS",
+        "md" => "Markdown",
+        "" => "No extension",
+        other => return other.to_uppercase(),
+    };
+    name.to_string()
+}
+
+/// Controls how [`format_files`] lays out each file.
+struct FormatOptions {
+    /// Precede the first file of each run of equal extensions with a
+    /// `===== Language =====` comment.
+    group_banners: bool,
+    /// Close each file with an `end <path>` comment.
+    footers: bool,
+    /// Put files with at most this many lines on their header line.
+    flatten_max_lines: Option<usize>,
+    /// Separate the comment delimiter from the path with a space (`// path`).
+    header_space: bool,
+    /// Precede each run of files sharing a directory with a `dir/` comment
+    /// and head them with their file names only.
+    dir_banners: bool,
+    /// Header lines by extension, with `{path}` standing for the path.
+    header_templates: HashMap<String, String>,
+    /// The index of the first test file, preceded by a `===== Tests =====`
+    /// comment, when tests come after the other files.
+    tests_from: Option<usize>,
+}
+
+impl FormatOptions {
+    /// The header template for `extension` or one of its aliases.
+    fn header_template(&self, extension: &str) -> Option<&str> {
+        std::iter::once(extension)
+            .chain(extension_aliases(extension).iter().copied())
+            .find_map(|ext| self.header_templates.get(ext))
+            .map(String::as_str)
+    }
+}
+
+/// The directory part of a display path, e.g. `src/` for `src/main.rs` and
+/// `./` for a top-level file.
+fn display_dir(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => format!("{}/", dir.to_string_lossy()),
+        _ => "./".to_string(),
+    }
+}
+
+/// Formats each file for the buffer, one at a time, so a streamed output
+/// never holds more than one formatted file.
+fn format_files<'a>(
+    files: &'a [CollectedFile],
+    options: &'a FormatOptions,
+) -> impl Iterator<Item = String> + 'a {
+    let dirs: Vec<String> = files.iter().map(|f| display_dir(&f.display_path)).collect();
+    let mut previous: Option<&str> = None;
+    files.iter().enumerate().map(move |(i, file)| {
+        // A directory banner only pays off for a run of at least two files.
+        let in_run = options.dir_banners
+            && ((i > 0 && dirs[i - 1] == dirs[i]) || dirs.get(i + 1) == Some(&dirs[i]));
+        let mut formatted = String::new();
+        if options.tests_from == Some(i) {
+            formatted.push_str(&get_comment_syntax(&file.language).comment("===== Tests ====="));
+            formatted.push_str("\n\n");
+            // The tests open their own language groups.
+            previous = None;
+        }
+        if options.group_banners && previous != Some(file.extension.as_str()) {
+            let banner = format!("===== {} =====", language_name(&file.extension));
+            formatted.push_str(&get_comment_syntax(&file.language).comment(&banner));
+            formatted.push_str("\n\n");
+        }
+        previous = Some(&file.extension);
+        if in_run {
+            // A language banner interrupts the run, so repeat the directory after it.
+            if i == 0 || dirs[i - 1] != dirs[i] || !formatted.is_empty() {
+                formatted.push_str(
+                    &get_comment_syntax(&file.language)
+                        .comment_with(&dirs[i], if options.header_space { " " } else { "" }),
+                );
+                formatted.push_str("\n\n");
+            }
+            let name = Path::new(&file.display_path).file_name().map_or_else(
+                || file.display_path.clone(),
+                |n| n.to_string_lossy().into_owned(),
+            );
+            formatted.push_str(&file.format_as(&name, options));
+        } else {
+            formatted.push_str(&file.format(options));
+        }
+        formatted
+    })
+}
+
+/// Formats every line of every file as `path:number: line`, like `grep -Hn`.
+///
+/// Line numbers count the lines of the collected content, so they match the
+/// original file only when nothing (e.g. `--head`) removed lines before them.
+fn format_prefixed_lines(files: &[CollectedFile]) -> String {
+    let mut out = String::new();
+    for file in files {
+        for (i, line) in file.content.lines().enumerate() {
+            out.push_str(&format!("{}:{}:", file.display_path, i + 1));
+            if !line.is_empty() {
+                out.push(' ');
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The format an output file's extension implies, when `--format` isn't given.
+fn format_for_path(path: &str) -> Option<OutputFormat> {
+    match file_extension(Path::new(path)).as_str() {
+        "md" | "markdown" => Some(OutputFormat::Markdown),
+        "xml" => Some(OutputFormat::Xml),
+        "json" => Some(OutputFormat::Json),
+        _ => None,
+    }
+}
+
+/// The `--title` line that opens the buffer: a comment, or a heading in
+/// Markdown. XML and JSON carry the title inside the document instead.
+fn title_banner(title: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Comments => format!("// {}\n\n", title),
+        OutputFormat::Markdown => format!("# {}\n\n", title),
+        OutputFormat::Repomix => format!("{}\n\n", title),
+        OutputFormat::Xml | OutputFormat::Json => String::new(),
+    }
+}
+
+/// The JSON layout: an object with the `--title`, the summary blocks as
+/// plain text, the tree and the files, each with its path, language, size
+/// and content (and, with `--with-hash`, its `sha256`).
+fn format_json(files: &[CollectedFile], tree: &str, title: Option<&str>, summary: &str) -> String {
+    let files: Vec<serde_json::Value> = files
+        .iter()
+        .map(|file| {
+            let mut entry = serde_json::json!({
+                "path": file.display_path,
+                "lang": file.language,
+                "bytes": file.content.len(),
+                "lines": file.content.lines().count(),
+                "content": file.content,
+            });
+            if let Some(sha256) = &file.sha256 {
+                entry["sha256"] = sha256.as_str().into();
+            }
+            entry
+        })
+        .collect();
+    let document = serde_json::json!({
+        "title": title,
+        "summary": (!summary.is_empty()).then_some(summary),
+        "tree": tree,
+        "files": files,
+    });
+    serde_json::to_string_pretty(&document).expect("JSON values always serialize") + "\n"
+}
+
+/// The Markdown layout: the tree, then each file under a `##` heading, or
+/// with `by_language` under a `##` heading per language and a `###` heading
+/// of its own.
+fn format_markdown(
+    files: &[CollectedFile],
+    tree: &str,
+    tests_from: Option<usize>,
+    by_language: bool,
+) -> String {
+    let mut out = format!("# Codebase\n\n```\n{}```\n", tree);
+    let mut previous = None;
+    for (i, file) in files.iter().enumerate() {
+        if tests_from == Some(i) {
+            out.push_str("\n# Tests\n");
+            previous = None;
+        }
+        if !by_language {
+            out.push_str(&markdown_file(file, "##"));
+            continue;
+        }
+        let language = language_name(&file.extension);
+        if previous.as_ref() != Some(&language) {
+            out.push_str(&format!("\n## {}\n", language));
+        }
+        out.push_str(&markdown_file(file, "###"));
+        previous = Some(language);
+    }
+    out
+}
+
+/// One file under a `heading` (e.g. `##`) naming its path, in a fenced block.
+fn markdown_file(file: &CollectedFile, heading: &str) -> String {
+    let fence = markdown_fence(&file.content);
+    format!(
+        "\n{} {}\n\n{}{}\n{}\n{}\n",
+        heading,
+        file.display_path,
+        fence,
+        file.language,
+        file.content.trim_end(),
+        fence
+    )
+}
+
+/// A code fence for `content`: longer than any backtick run in it, so it
+/// can't be closed early.
+fn markdown_fence(content: &str) -> String {
+    let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+/// One `--interleave` section: a directory's part of the tree and the
+/// indices of its files in the collected order.
+struct DirSection {
+    fragment: String,
+    files: Vec<usize>,
+}
+
+/// Lays out `sections` for the comments format, each tree fragment as `//`
+/// comment lines above its directory's formatted files.
+fn format_interleaved(sections: &[DirSection], formatted: &[String]) -> String {
+    let mut out = String::new();
+    for section in sections {
+        for line in section.fragment.lines() {
+            out.push_str(&format!("// {}\n", line));
+        }
+        out.push('\n');
+        for &i in &section.files {
+            out.push_str(&formatted[i]);
+        }
+    }
+    out
+}
+
+/// Like [`format_markdown`], with each tree fragment fenced above its
+/// directory's files instead of the whole tree up front.
+fn format_markdown_interleaved(sections: &[DirSection], files: &[CollectedFile]) -> String {
+    let mut out = String::from("# Codebase\n");
+    for section in sections {
+        out.push_str(&format!("\n```\n{}```\n", section.fragment));
+        for &i in &section.files {
+            out.push_str(&markdown_file(&files[i], "##"));
+        }
+    }
+    out
+}
+
+/// Orders paths component by component, so they match the tree's ordering.
+fn compare_paths(a: &Path, b: &Path, sort: TreeSort) -> std::cmp::Ordering {
+    let names = |path: &Path| {
+        path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let (a, b) = (names(a), names(b));
+    a.iter()
+        .zip(&b)
+        .map(|(a, b)| sort.compare(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// Lists `paths` one per line, indented like the tree and sorted as it would
+/// be, ending with `... and N more files` past `max_entries`.
+fn compact_tree(paths: &[PathBuf], sort: TreeSort, max_entries: Option<usize>) -> String {
+    let mut paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    paths.sort_by(|a, b| compare_paths(a, b, sort));
+    let shown = max_entries.unwrap_or(paths.len()).min(paths.len());
+    let mut out = String::new();
+    for path in &paths[..shown] {
+        let components: Vec<_> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        out.push_str(&format!("    {}\n", components.join("/")));
+    }
+    if shown < paths.len() {
+        out.push_str(&format!("    ... and {} more files\n", paths.len() - shown));
+    }
+    out
+}
+
+/// Splits `text` into chunks of at most `max_bytes`, breaking only between
+/// lines. A line longer than the limit gets a chunk of its own.
+fn split_into_chunks(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    for line in text.split_inclusive('\n') {
+        if end > start && end - start + line.len() > max_bytes {
+            chunks.push(&text[start..end]);
+            start = end;
+        }
+        end += line.len();
+    }
+    if end > start {
+        chunks.push(&text[start..end]);
+    }
+    chunks
+}
+
+/// Copies each chunk to the clipboard in turn, waiting for Enter in between
+/// so a clipboard manager (or the user) can pick each one up.
+fn copy_in_chunks(
+    clipboard: &mut SystemClipboard,
+    chunks: &[&str],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(
+            "--clipboard-chunks waits for Enter between parts, so it needs an interactive terminal"
+                .into(),
+        );
+    }
+    for (i, chunk) in chunks.iter().enumerate() {
+        clipboard.set_text(chunk)?;
+        if i + 1 < chunks.len() {
+            eprint!(
+                "Copied part {}/{}. Press Enter to copy part {}/{}...",
+                i + 1,
+                chunks.len(),
+                i + 2,
+                chunks.len()
+            );
+            io::stdin().lock().read_line(&mut String::new())?;
+        }
+    }
+    Ok(())
+}
+
+/// Target indentation for `--indent`; the number is the width of one tab stop.
+#[derive(Clone, Copy)]
+enum IndentMode {
+    Tabs(usize),
+    Spaces(usize),
+}
+
+fn parse_indent(value: &str) -> Result<IndentMode, String> {
+    let (kind, width) = value.split_once(':').unwrap_or((value, "4"));
+    let width = match width.parse::<usize>() {
+        Ok(width) if width > 0 => width,
+        _ => return Err(format!("invalid indent width {:?}", width)),
+    };
+    match kind {
+        "tabs" => Ok(IndentMode::Tabs(width)),
+        "spaces" => Ok(IndentMode::Spaces(width)),
+        _ => Err("expected tabs, tabs:N, spaces or spaces:N".to_string()),
+    }
+}
+
+/// Rewrites the leading whitespace of each line with tabs or spaces, keeping
+/// its visual width. Whitespace after the first other character is left alone.
+fn normalize_indent(content: &str, mode: IndentMode) -> String {
+    let (IndentMode::Tabs(width) | IndentMode::Spaces(width)) = mode;
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let rest = line.trim_start_matches([' ', '\t']);
+        let mut column = 0;
+        for c in line[..line.len() - rest.len()].chars() {
+            column = if c == '\t' {
+                (column / width + 1) * width
+            } else {
+                column + 1
+            };
+        }
+        match mode {
+            IndentMode::Tabs(_) => {
+                out.push_str(&"\t".repeat(column / width));
+                out.push_str(&" ".repeat(column % width));
+            }
+            IndentMode::Spaces(_) => out.push_str(&" ".repeat(column)),
+        }
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Shortens every line of `content` longer than `max` characters, either by
+/// cutting it off with `…` or by hard-wrapping it into `max`-character lines.
+fn limit_line_length(content: &str, max: usize, mode: LineLengthMode) -> String {
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        if text.chars().count() <= max {
+            out.push_str(line);
+            continue;
+        }
+        match mode {
+            LineLengthMode::Truncate => {
+                out.extend(text.chars().take(max));
+                out.push('…');
+            }
+            LineLengthMode::Wrap => {
+                let chars: Vec<char> = text.chars().collect();
+                let wrapped: Vec<String> = chars
+                    .chunks(max)
+                    .map(|chunk| chunk.iter().collect())
+                    .collect();
+                out.push_str(&wrapped.join("\n"));
+            }
+        }
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Keeps the first `n` lines (or the last `n` when `from_end` is set), marking
+/// the cut with a comment saying how many lines were dropped.
+fn truncate_lines(content: &str, n: usize, from_end: bool, style: &CommentStyle) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() <= n {
+        return content.to_string();
+    }
+
+    let marker = style.comment(&format!("... truncated {} lines ...", lines.len() - n));
+    let mut out = String::new();
+    if from_end {
+        out.push_str(&marker);
+        out.push('\n');
+        for line in &lines[lines.len() - n..] {
+            out.push_str(line);
+            out.push('\n');
+        }
+    } else {
+        for line in &lines[..n] {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push_str(&marker);
+        out.push('\n');
+    }
+    out
+}
+
+/// A `--line-range` selection: lines `start..=end` (1-based) of `path`.
+#[derive(Clone)]
+struct LineRange {
+    path: PathBuf,
+    start: usize,
+    end: usize,
+}
+
+fn parse_line_range(value: &str) -> Result<LineRange, String> {
+    let (path, range) = value
+        .rsplit_once(':')
+        .ok_or("expected FILE:START-END, e.g. src/main.rs:100-140")?;
+    let (start, end) = range.split_once('-').ok_or("expected a START-END range")?;
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid line number {:?}", n))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start == 0 || end < start {
+        return Err(format!(
+            "invalid range {}-{}: lines start at 1 and the end can't precede the start",
+            start, end
+        ));
+    }
+    Ok(LineRange {
+        path: PathBuf::from(path.strip_prefix("./").unwrap_or(path)),
+        start,
+        end,
+    })
+}
+
+/// Keeps only the lines of `ranges`, each run introduced by a `lines START-END` comment.
+///
+/// Ranges running past the end of the file are clamped, and ranges starting
+/// after it are dropped, with a warning either way.
+fn select_line_ranges(
+    content: &str,
+    ranges: &[&LineRange],
+    path: &Path,
+    style: &CommentStyle,
+) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut ranges = ranges.to_vec();
+    ranges.sort_by_key(|range| (range.start, range.end));
+
+    let mut out = String::new();
+    for range in ranges {
+        if range.start > lines.len() {
+            eprintln!(
+                "Line range {}-{} is past the end of {:?} ({} lines); ignoring it",
+                range.start,
+                range.end,
+                path,
+                lines.len()
+            );
+            continue;
+        }
+        let end = if range.end > lines.len() {
+            eprintln!(
+                "Line range {}-{} runs past the end of {:?}; stopping at line {}",
+                range.start,
+                range.end,
+                path,
+                lines.len()
+            );
+            lines.len()
+        } else {
+            range.end
+        };
+
+        out.push_str(&style.comment(&format!("lines {}-{}", range.start, end)));
+        out.push('\n');
+        for line in &lines[range.start - 1..end] {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Directory names that are never descended into.
+const DEFAULT_EXCLUDED_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    "build",
+    "dist",
+    "venv",
+    "env",
+    ".venv",
+    ".env",
+    // Version control metadata, hidden anyway, but kept out even when reached.
+    ".git",
+    ".hg",
+    ".svn",
+    ".bzr",
+];
+
+fn print_defaults() -> Result<(), ignore::Error> {
+    println!("Excluded directories (add more with -x):");
+    for dir in DEFAULT_EXCLUDED_DIRS {
+        println!("    {}", dir);
+    }
+
+    let mut types_builder = TypesBuilder::new();
+    types_builder.add_defaults();
+    println!();
+    println!("Known file types (-e matches extensions; without -e every text file is collected):");
+    for def in types_builder.build()?.definitions() {
+        println!("    {}: {}", def.name(), def.globs().join(", "));
+    }
+
+    println!();
+    println!("Extension aliases (-e with one matches all):");
+    for group in EXTENSION_ALIASES {
+        println!("    {}", group.join(", "));
+    }
+    Ok(())
+}
+
+/// The lowercased `-e` extensions, with their aliases.
+fn selected_extensions(args: &Cli) -> Vec<String> {
+    let mut extensions: Vec<String> = Vec::new();
+    for ext in args.extensions.iter().map(|s| s.to_lowercase()) {
+        for alias in extension_aliases(&ext) {
+            if !extensions.iter().any(|e| e == alias) {
+                extensions.push(alias.to_string());
+            }
+        }
+        if !extensions.contains(&ext) {
+            extensions.push(ext);
+        }
+    }
+    extensions
+}
+
+/// How many of the most common skipped extensions --report-unsupported-extensions lists.
+const UNSUPPORTED_EXTENSIONS_SHOWN: usize = 10;
+
+/// Extensions that name the same kind of file; `-e` with one matches all of a group.
+const EXTENSION_ALIASES: &[&[&str]] = &[
+    &["yaml", "yml"],
+    &["html", "htm"],
+    &["md", "markdown"],
+    &["cpp", "cc", "cxx"],
+    &["hpp", "hh", "hxx"],
+];
+
+/// The extensions `ext` also stands for, including itself; just `ext` if it has no aliases.
+fn extension_aliases(ext: &str) -> &[&str] {
+    EXTENSION_ALIASES
+        .iter()
+        .find(|group| group.contains(&ext))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Directory names whose contents are considered tests.
+const TEST_DIR_NAMES: &[&str] = &["tests", "__tests__", "spec"];
+
+/// File name prefixes that mark a test file.
+const TEST_FILE_PREFIXES: &[&str] = &["test_"];
+
+/// File name suffixes that mark a test file.
+const TEST_FILE_SUFFIXES: &[&str] = &[
+    "_test.rs", "_test.py", ".spec.ts", ".test.ts", ".spec.js", ".test.js",
+];
+
+/// File names of dependency lockfiles, matched exactly.
+const LOCKFILE_NAMES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "pdm.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "mix.lock",
+    "flake.lock",
+    "Package.resolved",
+    "pubspec.lock",
+];
+
+fn is_lockfile(name: &str) -> bool {
+    LOCKFILE_NAMES.contains(&name)
+}
+
+/// File names of project manifests and build or tool configs, matched exactly,
+/// which `--config-first` puts ahead of the sources.
+const PROJECT_CONFIG_NAMES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "tsconfig.json",
+    "deno.json",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements.txt",
+    "Pipfile",
+    "go.mod",
+    "Gemfile",
+    "composer.json",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "settings.gradle",
+    "settings.gradle.kts",
+    "CMakeLists.txt",
+    "meson.build",
+    "Makefile",
+    "mix.exs",
+    "pubspec.yaml",
+    "Package.swift",
+    "flake.nix",
+];
+
+fn is_project_config(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .is_some_and(|name| PROJECT_CONFIG_NAMES.contains(&name))
+}
+
+/// How many leading lines `looks_generated` inspects.
+const GENERATED_HEADER_LINES: usize = 5;
+
+/// Lowercased phrases that mark a file as generated when they appear in a
+/// comment near its top, e.g. Go's `// Code generated by ...; DO NOT EDIT.`
+const GENERATED_MARKERS: &[&str] = &[
+    "code generated",
+    "do not edit",
+    "auto-generated",
+    "autogenerated",
+    "automatically generated",
+    "@generated",
+    "this file was generated",
+    "this file is generated",
+];
+
+/// Line starts that look like a comment in some language.
+const COMMENT_STARTS: &[&str] = &["//", "#", "/*", "*", "<!--", "--", ";", "\"\"\""];
+
+/// Whether the first few lines of `head` contain a comment saying the file is generated.
+fn looks_generated(head: &str) -> bool {
+    head.lines().take(GENERATED_HEADER_LINES).any(|line| {
+        let line = line.trim_start();
+        COMMENT_STARTS.iter().any(|start| line.starts_with(start)) && {
+            let line = line.to_lowercase();
+            GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+        }
+    })
+}
+
+fn looks_like_test(path: &Path) -> bool {
+    let in_test_dir = path
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.components())
+        .any(|comp| match comp {
+            Component::Normal(name) => name
+                .to_str()
+                .is_some_and(|name| TEST_DIR_NAMES.contains(&name)),
+            _ => false,
+        });
+    if in_test_dir {
+        return true;
+    }
+
+    let file_name = match path.file_name().and_then(|s| s.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    (TEST_FILE_PREFIXES
+        .iter()
+        .any(|prefix| file_name.starts_with(prefix))
+        && file_name.ends_with(".py"))
+        || TEST_FILE_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A line containing one of the `--todo-markers`.
+struct Todo {
+    /// 1-based line number in the file as read.
+    line: usize,
+    text: String,
+}
+
+/// Finds the lines of `content` that contain a marker matched by `markers`.
+fn find_todos(content: &str, markers: &Regex) -> Vec<Todo> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| markers.is_match(line))
+        .map(|(i, line)| Todo {
+            line: i + 1,
+            text: line.trim().to_string(),
+        })
+        .collect()
+}
+
+/// Lists every TODO of `files` as `path:line: text`, each line starting with `prefix`.
+fn todo_list(files: &[CollectedFile], prefix: &str) -> String {
+    let mut list = String::new();
+    for file in files {
+        for todo in &file.todos {
+            list.push_str(&format!(
+                "{}{}:{}: {}\n",
+                prefix, file.display_path, todo.line, todo.text
+            ));
+        }
+    }
+    list
+}
+
+/// Lists each directory holding some of `files`, by their header paths, with
+/// how many files of each extension it holds, each line starting with `prefix`.
+fn table_of_contents(files: &[CollectedFile], prefix: &str) -> String {
+    let mut dirs: BTreeMap<String, BTreeMap<&str, usize>> = BTreeMap::new();
+    for file in files {
+        let dir = match Path::new(&file.display_path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => {
+                format!("{}/", dir.to_string_lossy().replace('\\', "/"))
+            }
+            _ => "./".to_string(),
+        };
+        *dirs
+            .entry(dir)
+            .or_default()
+            .entry(file.extension.as_str())
+            .or_default() += 1;
+    }
+    let width = dirs
+        .keys()
+        .map(|dir| dir.chars().count())
+        .max()
+        .unwrap_or(0);
+    let count_width = dirs
+        .values()
+        .map(|extensions| extensions.values().sum::<usize>().to_string().len())
+        .max()
+        .unwrap_or(0);
+    let mut toc = String::new();
+    for (dir, extensions) in &dirs {
+        let count: usize = extensions.values().sum();
+        let breakdown: Vec<String> = extensions
+            .iter()
+            .map(|(extension, n)| match *extension {
+                "" => format!("(none) {}", n),
+                extension => format!("{} {}", extension, n),
+            })
+            .collect();
+        toc.push_str(&format!(
+            "{}{:<width$}  {:>count_width$} {} ({})\n",
+            prefix,
+            dir,
+            count,
+            if count == 1 { "file" } else { "files" },
+            breakdown.join(", "),
+        ));
+    }
+    toc
+}
+
+/// Lists the imports of each file that has any, as `path -> a, b`, each line
+/// starting with `prefix`.
+fn import_graph(files: &[CollectedFile], prefix: &str) -> String {
+    let mut graph = String::new();
+    for file in files {
+        match imports::imports(&file.extension, &file.content) {
+            Some(found) if !found.is_empty() => graph.push_str(&format!(
+                "{}{} -> {}\n",
+                prefix,
+                file.display_path,
+                found.join(", ")
+            )),
+            _ => {}
+        }
+    }
+    graph
+}
+
+fn print_todos(files: &[CollectedFile]) {
+    let list = todo_list(files, "    ");
+    if list.is_empty() {
+        info!("No TODOs found.");
+    } else {
+        info!("TODOs:\n{}", list.trim_end());
+    }
+}
+
+/// Writes `root` as JSON to `path`, or to stdout when it is `-`.
+fn write_tree_json(path: &str, root: &TreeNode, sort: TreeSort) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(&root.to_json(sort))?;
+    if path == "-" {
+        println!("{}", json);
+        Ok(())
+    } else {
+        fs::write(path, json + "\n")
+    }
+}
+
+fn print_tree(tree: &str) {
+    if log::enabled(Verbosity::Normal) {
+        log::progress!("Copied Files Tree:");
+        for line in tree.lines() {
+            log::progress!("{}", line);
+        }
+    }
+}
+
+fn print_summary_table(files: &[CollectedFile], raw_bytes: bool) {
+    let header_style = log::NUMBER;
+    for (i, line) in extension_table(&extension_totals(files), raw_bytes)
+        .into_iter()
+        .enumerate()
+    {
+        if i == 0 {
+            notice!(
+                "{}{}{}",
+                header_style.render(),
+                line,
+                header_style.render_reset()
+            );
+        } else {
+            notice!("{}", line);
+        }
+    }
+}
+
+/// Writes the `--summary-to-file` record of a run: the summary lines, then
+/// the per-extension table and the collected files, or all of it as JSON
+/// with `--stats-json`.
+fn write_summary_file(
+    path: &str,
+    args: &Cli,
+    source: &str,
+    files: &[CollectedFile],
+) -> io::Result<()> {
+    let summary = log::take_recorded_summary();
+    let totals = extension_totals(files);
+    let relative = |file: &CollectedFile| file.relative_path.to_string_lossy().replace('\\', "/");
+    let text = if args.stats_json {
+        let mut stats = size_stats(&totals);
+        stats["source"] = source.into();
+        stats["summary"] = summary.into();
+        stats["paths"] = files
+            .iter()
+            .map(|file| {
+                serde_json::json!({
+                    "path": relative(file),
+                    "bytes": file.content.len(),
+                    "extension": file.extension,
+                })
+            })
+            .collect::<Vec<_>>()
+            .into();
+        serde_json::to_string_pretty(&stats)? + "\n"
+    } else {
+        let mut text = format!("Source: {}\nCollected {} files.\n", source, files.len());
+        for line in summary {
+            text += &line;
+            text.push('\n');
+        }
+        if !files.is_empty() {
+            text.push('\n');
+            for line in extension_table(&totals, args.raw_bytes) {
+                text += line.trim_end();
+                text.push('\n');
+            }
+            text.push('\n');
+            for file in files {
+                text += &format!(
+                    "{} ({})\n",
+                    relative(file),
+                    format_size(file.content.len(), args.raw_bytes)
+                );
+            }
+        }
+        text
+    };
+    fs::write(path, text)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // --all-text replaces a CODE_COLLECTOR_EXTENSIONS default, but not a -e given with it.
+    if args.all_text {
+        if matches.value_source("extensions") == Some(ValueSource::CommandLine) {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "the argument '--all-text' cannot be used with '--extensions <EXTENSION>'",
+                )
+                .exit();
+        }
+        args.extensions.clear();
+    }
+
+    let splits = args.split_output.is_some() || args.chunk_tokens.is_some();
+    if splits
+        && args
+            .format
+            .is_some_and(|format| format != OutputFormat::Comments)
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--split-output and --chunk-tokens only support --format comments",
+            )
+            .exit();
+    }
+    if args.dedupe_ignore_whitespace && !matches!(args.dedupe_by, Some(DedupeBy::Content)) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--dedupe-ignore-whitespace only applies to --dedupe-by content",
+            )
+            .exit();
+    }
+    if args.stats_json && !args.dry_run && args.summary_to_file.is_none() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--stats-json needs --dry-run or --summary-to-file",
+            )
+            .exit();
+    }
+
+    match args.color {
+        ColorWhen::Auto => {}
+        ColorWhen::Always => anstream::ColorChoice::Always.write_global(),
+        ColorWhen::Never => anstream::ColorChoice::Never.write_global(),
+    }
+
+    log::set_verbosity(if args.quiet {
+        Verbosity::Quiet
+    } else if args.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+    // --print0 and --dry-run print only the list, and mostly skip what the counts are about.
+    log::set_summary_position(if args.print0 || args.dry_run {
+        SummaryPosition::None
+    } else {
+        args.summary
+    });
+    log::set_explain(args.explain);
+    if args.stdout || args.manifest_only.is_some() || args.tree_json.as_deref() == Some("-") {
+        log::take_stdout();
+    }
+    if let Some(prefix) = &args.default_comment {
+        DEFAULT_COMMENT.get_or_init(|| prefix.clone().leak());
+    }
+    if let Some(model) = &args.tokenizer {
+        tokenizer::set_model(model);
+    }
+
+    if let Some(Command::Completions { shell }) = args.command {
+        clap_complete::generate(
+            shell,
+            &mut Cli::command(),
+            "code_collector",
+            &mut io::stdout(),
+        );
+        return Ok(());
+    }
+
+    if args.list_defaults {
+        print_defaults()?;
+        return Ok(());
+    }
+
+    if args.batch {
+        return run_batch(&args);
+    }
+
+    if let Some(source) = &args.unpack {
+        return run_unpack(&args, source);
+    }
+
+    run(&args)?;
+    Ok(())
+}
+
+/// Reads directories from stdin, one per line, and collects each into
+/// `<name>.txt` in the current directory. A directory that fails is reported
+/// and skipped rather than aborting the batch.
+fn run_batch(args: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut used_names = HashSet::new();
+    let mut total = 0;
+    let mut failed = 0;
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        total += 1;
+
+        let directory = match expand_path(line) {
+            Ok(directory) if Path::new(&directory).is_dir() => directory,
+            Ok(directory) => {
+                eprintln!("{}: not a directory", directory);
+                failed += 1;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("{}: {}", line, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        // Name the output after the directory, numbering repeats (e.g. several `src`).
+        let name = fs::canonicalize(&directory)
+            .ok()
+            .and_then(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "root".to_string());
+        let mut output = format!("{}.txt", name);
+        let mut n = 2;
+        while !used_names.insert(output.clone()) {
+            output = format!("{}-{}.txt", name, n);
+            n += 1;
+        }
+
+        let mut directory_args = args.clone();
+        directory_args.directory = Some(directory.clone());
+        directory_args.output = Some(output.clone());
+        directory_args.no_clipboard = true;
+        match run(&directory_args) {
+            Ok(0) => info!(
+                "{}: nothing collected, so {} wasn't written",
+                directory, output
+            ),
+            Ok(count) => info!("{}: collected {} files into {}", directory, count, output),
+            Err(e) => {
+                eprintln!("{}: {}", directory, e);
+                failed += 1;
+            }
+        }
+    }
+
+    info!(
+        "Batch finished: {} of {} directories collected.",
+        total - failed,
+        total
+    );
+    if failed > 0 {
+        return Err(format!("{} of {} directories failed", failed, total).into());
+    }
+    Ok(())
+}
+
+/// Writes the files of the buffer in `source` under `args.directory`, for
+/// `--unpack`. Files that exist and differ are only replaced with
+/// `--overwrite`, and nothing is written with `--dry-run`.
+fn run_unpack(args: &Cli, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let buffer = match source {
+        "-" => io::read_to_string(io::stdin())?,
+        "@clipboard" => SystemClipboard::open(args.clipboard_backend)?.get_text()?,
+        path => fs::read_to_string(path)?,
+    };
+    let sections = unpack::sections(&buffer);
+    if sections.is_empty() {
+        return Err(format!("found no file headers in {}", source).into());
+    }
+
+    let target = Path::new(args.directory.as_deref().unwrap_or("."));
+    let mut written = 0;
+    let mut left = 0;
+    let mut seen = HashSet::new();
+    for section in sections {
+        let path = &section.path;
+        let inside = path
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_) | Component::CurDir));
+        if !inside {
+            notice!(
+                "Not unpacking {}, which would land outside {}",
+                path.display(),
+                target.display()
+            );
+            left += 1;
+            continue;
+        }
+        if !seen.insert(path.clone()) {
+            notice!(
+                "{} is in the buffer more than once; only the first copy is unpacked",
+                path.display()
+            );
+            continue;
+        }
+        if section.unchanged {
+            verbose!(
+                "- skipped {} (unchanged, so not in the buffer)",
+                path.display()
+            );
+            left += 1;
+            continue;
+        }
+        let destination = target.join(path);
+        let exists = destination.exists();
+        if exists
+            && fs::read_to_string(&destination).is_ok_and(|current| current == section.content)
+        {
+            verbose!("- skipped {} (already up to date)", path.display());
+            left += 1;
+            continue;
+        }
+        if exists && !args.overwrite {
+            notice!(
+                "{} exists and differs from the buffer; --overwrite replaces it",
+                path.display()
+            );
+            left += 1;
+            continue;
+        }
+        let action = if exists { "overwrite" } else { "create" };
+        if args.dry_run {
+            info!("Would {} {}", action, path.display());
+        } else {
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&destination, &section.content)?;
+            verbose!("- {}d {}", action, path.display());
+        }
+        written += 1;
+    }
+
+    info!(
+        "{} {} files under {}; left {} as they were.",
+        if args.dry_run { "Would write" } else { "Wrote" },
+        written,
+        target.display(),
+        left
+    );
+    Ok(())
+}
+
+/// Collects `args.directory` (or `args.archive`) and delivers the buffer,
+/// returning the number of files in it.
+fn run(args: &Cli) -> Result<usize, Box<dyn std::error::Error>> {
+    let directory = args
+        .directory
+        .as_deref()
+        .map(|d| normalize_path(Path::new(d)).to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some(range) = &args.git_diff {
+        return run_git_diff(args, &directory, range.as_deref());
+    }
+    if args.summary_to_file.is_some() {
+        log::record_summary();
+    }
+    let mut timings = Timings::new();
+    let single_file = Path::new(&directory).is_file();
+    // A --depth for this directory, then --max-depth.
+    let max_depth = args
+        .depth
+        .iter()
+        .rev()
+        .find(|(dir, _)| same_directory(Path::new(dir), Path::new(&directory)))
+        .map(|(_, depth)| *depth)
+        .or(args.max_depth)
+        .map(NonZeroUsize::get);
+    if args.files_from.as_deref() == Some("-") && args.clipboard_chunks.is_some() {
+        return Err(
+            "--files-from - takes stdin, which --clipboard-chunks needs for its prompts".into(),
+        );
+    }
+    // Read a piped list before anything else touches the terminal or clipboard.
+    let listed_files = match &args.files_from {
+        Some(source) => Some(
+            read_file_list(source)
+                .map_err(|e| format!("could not read --files-from {}: {}", source, e))?,
+        ),
+        None => None,
+    };
+    let input_spec = args
+        .input_json
+        .as_deref()
+        .map(read_input_spec)
+        .transpose()?;
+    // Ranges from --line-range and from --input-json.
+    let line_ranges: Vec<LineRange> = args
+        .line_range
+        .iter()
+        .cloned()
+        .chain(
+            input_spec
+                .iter()
+                .flat_map(|spec| spec.ranges.iter().cloned()),
+        )
+        .collect();
+    if !args.print0 && !args.dry_run {
+        match &args.archive {
+            Some(archive_path) => info!("Processing archive: {}", archive_path),
+            None if single_file => info!("Processing file: {}", directory),
+            None => info!("Processing directory: {}", directory),
+        }
+    }
+
+    // Paths are shown relative to this directory; a single file is shown by
+    // its name relative to its parent.
+    let base_dir = match Path::new(&directory).parent() {
+        Some(parent) if single_file && !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ if single_file => PathBuf::from("."),
+        _ => PathBuf::from(&directory),
+    };
+
+    let extensions = selected_extensions(args);
+
+    let mut types_builder = TypesBuilder::new();
+
+    if !extensions.is_empty() {
+        for ext in &extensions {
+            let pattern = format!("*.{}", ext);
+            types_builder.add(ext, &pattern)?;
+            types_builder.select(ext);
+        }
+    } else {
+        types_builder.add_defaults();
+    }
+
+    let types_matcher = types_builder.build()?;
+
+    let renames = match &args.rename_map {
+        Some(path) => parse_rename_map(&fs::read_to_string(path)?)
+            .map_err(|e| format!("invalid rename map {}: {}", path, e))?,
+        None => Vec::new(),
+    };
+
+    // Multi-line mode so `^` and `$` anchor to lines, as with grep itself.
+    let grep = args
+        .grep
+        .as_deref()
+        .map(|pattern| RegexBuilder::new(pattern).multi_line(true).build())
+        .transpose()?;
+    let skip_content = args
+        .skip_content
+        .as_deref()
+        .map(|pattern| RegexBuilder::new(pattern).multi_line(true).build())
+        .transpose()?;
+
+    let todo_markers = if args.highlight_todos {
+        let alternatives: Vec<String> =
+            args.todo_markers.iter().map(|m| regex::escape(m)).collect();
+        Some(Regex::new(&format!(r"\b(?:{})\b", alternatives.join("|")))?)
+    } else {
+        None
+    };
+
+    let mut collected: Vec<CollectedFile> = Vec::new();
+    let mut excluded_tests = 0;
+    let mut excluded_lockfiles = 0;
+
+    // With --ignore-case, names are lowercased on both sides of the comparison.
+    let ignore_case = args.ignore_case;
+    let dir_key = move |name: &str| {
+        if ignore_case {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    };
+
+    let mut excluded_dirs: HashSet<String> =
+        DEFAULT_EXCLUDED_DIRS.iter().map(|s| dir_key(s)).collect();
+
+    // Include user-specified directories to exclude
+    for dir in &args.exclude_dirs {
+        excluded_dirs.insert(dir_key(dir));
+    }
+    // The built-in excludes that ignore-file negations can open up; -x can't be overridden.
+    let mut yielding_dirs = excluded_dirs.clone();
+    for dir in &args.exclude_dirs {
+        yielding_dirs.remove(&dir_key(dir));
+    }
+
+    let mut dir_globs = GlobSetBuilder::new();
+    for pattern in &args.exclude_dir_globs {
+        dir_globs.add(
+            GlobBuilder::new(pattern.trim_matches('/'))
+                .literal_separator(true)
+                .case_insensitive(args.ignore_case)
+                .build()?,
+        );
+    }
+    let dir_globs = dir_globs.build()?;
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    // For --emit-empty-tree, relative to the collected directory.
+    let mut walked_dirs: Vec<PathBuf> = Vec::new();
+    // What --explain says about a file that made it through.
+    let inclusion = |extension: &str| {
+        if extensions.is_empty() {
+            "included".to_string()
+        } else {
+            format!("included (extension {})", extension)
+        }
+    };
+
+    if let Some(spec) = &input_spec {
+        for path in &spec.paths {
+            let on_disk = base_dir.join(path);
+            if !on_disk.is_file() {
+                notice!(
+                    "--input-json lists {:?}, which isn't a file in {}",
+                    path,
+                    base_dir.display()
+                );
+                continue;
+            }
+            candidates.push(Candidate {
+                relative_path: path.clone(),
+                source: FileSource::Disk(on_disk),
+            });
+        }
+    } else if let Some(archive_path) = &args.archive {
+        for entry in archive::read_entries(Path::new(archive_path))? {
+            // Mirror the walker: skip hidden entries and excluded directories.
+            let in_excluded_dir = entry.path.components().enumerate().any(|(i, comp)| {
+                let name = comp.as_os_str().to_string_lossy();
+                let is_dir = i + 1 < entry.path.components().count();
+                name.starts_with('.') || (is_dir && excluded_dirs.contains(&dir_key(&name)))
+            });
+            let in_excluded_dir_glob = entry
+                .path
+                .ancestors()
+                .skip(1)
+                .any(|dir| !dir.as_os_str().is_empty() && dir_globs.is_match(dir));
+            let too_deep = max_depth.is_some_and(|depth| entry.path.components().count() > depth);
+            if in_excluded_dir || in_excluded_dir_glob || too_deep {
+                continue;
+            }
+            candidates.push(Candidate {
+                relative_path: entry.path,
+                source: FileSource::Archive(entry.data),
+            });
+        }
+    } else if single_file {
+        let path = PathBuf::from(&directory);
+        candidates.push(Candidate {
+            relative_path: path.file_name().map_or_else(|| path.clone(), PathBuf::from),
+            source: FileSource::Disk(path),
+        });
+    } else {
+        let mut walk_builder = WalkBuilder::new(&directory);
+        // The report and --explain need to see the files -e leaves out, so
+        // they're dropped by the extension check on candidates instead.
+        if !args.all_text && !args.report_unsupported_extensions && !args.explain {
+            walk_builder.types(types_matcher);
+        }
+        if args.use_npmignore {
+            walk_builder.add_custom_ignore_filename(".npmignore");
+        }
+        if args.use_dockerignore {
+            walk_builder.add_custom_ignore_filename(".dockerignore");
+        }
+        let declared_submodules = read_gitmodules(Path::new(&directory));
+        let hgignore = if args.respect_hgignore {
+            HgIgnore::load(Path::new(&directory))?
+        } else {
+            None
+        };
+        // The filter below takes these; --explain needs them afterwards too.
+        let explain_filters = args.explain.then(|| {
+            (
+                excluded_dirs.clone(),
+                dir_globs.clone(),
+                declared_submodules.clone(),
+                hgignore.clone(),
+            )
+        });
+        // Filled in by the walker's filter, which must be `Send + Sync + 'static`.
+        let submodules = Arc::new(Mutex::new(Vec::new()));
+        let seen_submodules = Arc::clone(&submodules);
+        let include_submodules = args.include_submodules;
+        let root = PathBuf::from(&directory);
+        let negations = Negations::new(&root, args.ignore_case);
+
+        walk_builder
+            .git_ignore(true)
+            .parents(!args.no_parent_ignores)
+            .hidden(true)
+            .ignore_case_insensitive(args.ignore_case)
+            .follow_links(args.follow_links)
+            .max_depth(max_depth)
+            .filter_entry(move |entry| {
+                let path = entry.path();
+                if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
+                    if path.is_dir()
+                        && excluded_dirs.contains(&dir_key(dir_name))
+                        && !(yielding_dirs.contains(&dir_key(dir_name))
+                            && negations.reaches_into(path))
+                    {
+                        return false;
+                    }
+                }
+                // Inside a built-in exclude a negation opened up, only what it names.
+                let in_opened_dir = entry.depth() > 1
+                    && path
+                        .strip_prefix(&root)
+                        .unwrap_or(path)
+                        .parent()
+                        .is_some_and(|parent| {
+                            parent.components().any(|comp| {
+                                yielding_dirs
+                                    .contains(&dir_key(&comp.as_os_str().to_string_lossy()))
+                            })
+                        });
+                if in_opened_dir {
+                    let is_dir = path.is_dir();
+                    let named = negations.is_whitelisted(path, is_dir)
+                        || (is_dir && negations.reaches_into(path));
+                    if !named {
+                        return false;
+                    }
+                }
+                if let Some(hgignore) = &hgignore {
+                    if entry.depth() > 0
+                        && hgignore.is_ignored(path.strip_prefix(&root).unwrap_or(path))
+                    {
+                        return false;
+                    }
+                }
+                if entry.depth() > 0 && path.is_dir() {
+                    let relative_dir = path.strip_prefix(&root).unwrap_or(path);
+                    if dir_globs.is_match(relative_dir) {
+                        return false;
+                    }
+                    if is_submodule(path, relative_dir, &declared_submodules) {
+                        seen_submodules
+                            .lock()
+                            .unwrap()
+                            .push(relative_dir.to_owned());
+                        return include_submodules;
+                    }
+                }
+                true
+            });
+        // The same walk with gitignore rules off, for the files --unignore-ext brings back.
+        let unignored_walker = (!args.unignore_ext.is_empty()).then(|| {
+            walk_builder
+                .clone()
+                .git_ignore(false)
+                .git_exclude(false)
+                .git_global(false)
+                .build()
+        });
+        let walker = walk_builder.build();
+
+        let mut walked = HashSet::new();
+
+        for result in walker {
+            let entry = result?;
+            let path = entry.path();
+
+            if args.show_gitignored_count || args.explain {
+                walked.insert(path.to_owned());
+            }
+            if args.emit_empty_tree && entry.depth() > 0 && path.is_dir() {
+                walked_dirs.push(path.strip_prefix(&directory)?.to_owned());
+            }
+
+            if path.is_file() {
+                candidates.push(Candidate {
+                    relative_path: path.strip_prefix(&directory)?.to_owned(),
+                    source: FileSource::Disk(path.to_owned()),
+                });
+            }
+        }
+
+        if let Some((excluded_dirs, dir_globs, declared_submodules, hgignore)) = explain_filters {
+            let mut custom_ignores = Vec::new();
+            if args.use_npmignore {
+                custom_ignores.push(".npmignore");
+            }
+            if args.use_dockerignore {
+                custom_ignores.push(".dockerignore");
+            }
+            let mut rules =
+                explain::IgnoreRules::new(Path::new(&directory), &custom_ignores, args.ignore_case);
+            for (path, is_dir) in explain::pruned_paths(
+                &directory,
+                &walked,
+                args.follow_links,
+                args.max_open_files.get(),
+                max_depth,
+            ) {
+                let relative_path = path.strip_prefix(&directory)?;
+                let name = relative_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                let reason = if name.starts_with('.') {
+                    "hidden".to_string()
+                } else if is_dir && excluded_dirs.contains(&dir_key(&name)) {
+                    format!("directory name {}", name)
+                } else if is_dir && dir_globs.is_match(relative_path) {
+                    "--exclude-dir-glob".to_string()
+                } else if is_dir && is_submodule(&path, relative_path, &declared_submodules) {
+                    "submodule; see --include-submodules".to_string()
+                } else if hgignore
+                    .as_ref()
+                    .is_some_and(|h| h.is_ignored(relative_path))
+                {
+                    "rule in .hgignore".to_string()
+                } else {
+                    match rules.matching_rule(&path, is_dir) {
+                        Some(rule) => format!("ignore rule {}", rule),
+                        None => "an ignore rule".to_string(),
+                    }
+                };
+                let suffix = if is_dir { "/" } else { "" };
+                explain!(
+                    format!("{}{}", relative_path.display(), suffix),
+                    "excluded ({})",
+                    reason
+                );
+            }
+        }
+
+        if let Some(walker) = unignored_walker {
+            let unignore: HashSet<String> = args
+                .unignore_ext
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect();
+            let walked_files: HashSet<PathBuf> = candidates
+                .iter()
+                .map(|candidate| candidate.relative_path.clone())
+                .collect();
+            let mut unignored = 0;
+            for result in walker {
+                let entry = result?;
+                let path = entry.path();
+                if !path.is_file() || !unignore.contains(&file_extension(path)) {
+                    continue;
+                }
+                let relative_path = path.strip_prefix(&directory)?.to_owned();
+                if !walked_files.contains(&relative_path) {
+                    verbose!("+ re-included gitignored {}", relative_path.display());
+                    unignored += 1;
+                    candidates.push(Candidate {
+                        relative_path,
+                        source: FileSource::Disk(path.to_owned()),
+                    });
+                }
+            }
+            summary!(
+                "Re-included {} gitignored files (--unignore-ext).",
+                unignored
+            );
+        }
+
+        let mut submodules = submodules.lock().unwrap().clone();
+        if !submodules.is_empty() && !args.print0 && !args.dry_run {
+            submodules.sort();
+            // The --unignore-ext walk reports them a second time.
+            submodules.dedup();
+            let list: Vec<String> = submodules
+                .iter()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .collect();
+            if args.include_submodules {
+                info!("Collected submodules: {}.", list.join(", "));
+            } else {
+                info!(
+                    "Skipped submodules: {} (use --include-submodules to collect them).",
+                    list.join(", ")
+                );
+            }
+        }
+
+        if args.show_gitignored_count && !args.print0 && !args.dry_run {
+            info!(
+                "Excluded {} files/dirs by ignore rules.",
+                count_ignored(
+                    &directory,
+                    &walked,
+                    args.follow_links,
+                    args.max_open_files.get(),
+                    max_depth,
+                )
+            );
+        }
+    }
+
+    if args.tracked_only {
+        let tracked = git_tracked_files(&base_dir)?;
+        candidates.retain(|candidate| {
+            let is_tracked = tracked.contains(&candidate.relative_path);
+            if !is_tracked {
+                log_skip(&candidate.relative_path, "not tracked by git");
+            }
+            is_tracked
+        });
+    }
+
+    let changed_hunks = match &args.changed_hunks {
+        Some(range) => {
+            let mut diff_args: Vec<String> = ["--relative", "-U0", "--no-prefix", "--no-color"]
+                .map(String::from)
+                .to_vec();
+            diff_args.extend(range.clone());
+            let changed = hunks::changed_lines(&git_diff(&base_dir, &diff_args)?);
+            candidates.retain(|candidate| {
+                let is_changed = changed.contains_key(&candidate.relative_path);
+                if !is_changed {
+                    log_skip(&candidate.relative_path, "unchanged");
+                }
+                is_changed
+            });
+            Some(changed)
+        }
+        None => None,
+    };
+
+    if let Some(listed) = &listed_files {
+        candidates.retain(|candidate| {
+            let is_listed = listed.contains(&candidate.relative_path);
+            if !is_listed {
+                log_skip(&candidate.relative_path, "not in --files-from");
+            }
+            is_listed
+        });
+        let found: HashSet<&Path> = candidates
+            .iter()
+            .map(|candidate| candidate.relative_path.as_path())
+            .collect();
+        let mut missing: Vec<&PathBuf> = listed
+            .iter()
+            .filter(|path| !found.contains(path.as_path()))
+            .collect();
+        missing.sort();
+        for path in missing {
+            eprintln!(
+                "--files-from lists {:?}, which wasn't found or is excluded",
+                path
+            );
+        }
+    }
+
+    // Archive entries aren't on disk, so their .gitattributes can't be consulted.
+    let gitattributes = if args.include_generated || args.archive.is_some() {
+        GitAttributes::default()
+    } else {
+        let mut dirs: Vec<&Path> = candidates
+            .iter()
+            .flat_map(|c| c.relative_path.ancestors().skip(1))
+            .collect();
+        dirs.sort_by_key(|dir| (dir.components().count(), dir.to_path_buf()));
+        dirs.dedup();
+        GitAttributes::load(&base_dir, dirs)
+    };
+    let mut excluded_generated = 0;
+    let mut excluded_generated_by_comment = 0;
+    let mut encodings: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut non_lf_files = Vec::new();
+    let mut note_encoding = |path: &Path, encoding: &'static str| {
+        notice!("Encoding of {:?}: {}", path, encoding);
+        *encodings.entry(encoding).or_default() += 1;
+    };
+    let mut folded = 0;
+    let modified_cutoff = args
+        .modified_within
+        .map(|within| SystemTime::now() - within);
+    let mut modified_matched = 0;
+    let mut newer_matched = 0;
+    let mut excluded_minified = 0;
+    let mut excluded_by_lines = 0;
+    let mut excluded_by_content = 0;
+    let mut truncated_files = 0;
+    // With --show-binaries-in-tree, the files skipped as binary.
+    let mut binary_paths = Vec::new();
+    // With --follow-links, the first path each real file was reached by.
+    let mut link_targets: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut repeated_targets = 0;
+    let open_files = OpenFileLimit::new(args.max_open_files.get());
+    let mut ranged_paths = HashSet::new();
+    let mut invalid_syntax = 0;
+    let mut secrets_found = Vec::new();
+    let mut license_headers_stripped = 0;
+
+    let cache = match &args.cache {
+        Some(cache_dir) => {
+            if args.clear_cache {
+                ReadCache::clear(Path::new(cache_dir))?;
+            }
+            Some(ReadCache::open(Path::new(cache_dir))?)
+        }
+        None => None,
+    };
+
+    let mut lang_map: HashMap<&str, &'static str> = HashMap::new();
+    for (extension, language) in &args.lang_map {
+        match language_extension(language) {
+            Some(target) => {
+                lang_map.insert(extension, target);
+            }
+            None => eprintln!(
+                "Unknown language {:?} in --lang-map {}={}; ignoring it",
+                language, extension, language
+            ),
+        }
+    }
+
+    timings.lap("walk");
+    // With --print0 or --dry-run, the size of each file, which isn't read.
+    let mut listed_sizes: HashMap<PathBuf, u64> = HashMap::new();
+    // With --snapshot, the SHA-256 of each file as read.
+    let mut read_hashes: HashMap<PathBuf, String> = HashMap::new();
+    // Archive entries have no .editorconfig on disk to consult.
+    let mut editorconfigs =
+        (args.respect_editorconfig && args.archive.is_none()).then(EditorConfigs::default);
+    let mut skipped_extensions: HashMap<String, usize> = HashMap::new();
+    for candidate in candidates {
+        let relative_path = candidate.relative_path.as_path();
+        let extension = file_extension(relative_path);
+        let language = lang_map
+            .get(extension.as_str())
+            .map_or_else(|| extension.clone(), |language| language.to_string());
+
+        if !extensions.is_empty() && !extensions.iter().any(|e| e == &extension) {
+            log_skip(relative_path, "extension");
+            *skipped_extensions.entry(extension).or_insert(0) += 1;
+            continue;
+        }
+
+        if args.exclude_tests && looks_like_test(relative_path) {
+            log_skip(relative_path, "test");
+            excluded_tests += 1;
+            continue;
+        }
+
+        if args.exclude_lockfiles
+            && relative_path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .is_some_and(is_lockfile)
+        {
+            log_skip(relative_path, "lockfile");
+            excluded_lockfiles += 1;
+            continue;
+        }
+
+        if gitattributes.is_generated_or_vendored(relative_path) {
+            log_skip(relative_path, "generated or vendored per .gitattributes");
+            excluded_generated += 1;
+            continue;
+        }
+
+        let size_limit = match args
+            .max_file_size_ext
+            .iter()
+            .rev()
+            .find(|(ext, _)| *ext == extension)
+        {
+            Some((ext, limit)) => {
+                limit.map(|limit| (limit, format!("--max-file-size-ext {}", ext)))
+            }
+            None => args
+                .max_file_size
+                .map(|limit| (limit, "--max-file-size".to_string())),
+        };
+        let metadata = match &candidate.source {
+            FileSource::Disk(path) => fs::metadata(path).ok(),
+            FileSource::Archive(_) => None,
+        };
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        let size = match &candidate.source {
+            FileSource::Disk(_) => metadata.as_ref().map_or(0, fs::Metadata::len),
+            FileSource::Archive(data) => data.len() as u64,
+        };
+        if let Some((limit, flag)) = size_limit {
+            if size > limit {
+                log_noted_skip(
+                    relative_path,
+                    &format!(
+                        "{} is over the {} limit of {}",
+                        format_size(size as usize, args.raw_bytes),
+                        flag,
+                        format_size(limit as usize, args.raw_bytes)
+                    ),
+                );
+                continue;
+            }
+        }
+
+        if let FileSource::Disk(path) = &candidate.source {
+            if let Some(cutoff) = modified_cutoff {
+                if modified.is_none_or(|m| m < cutoff) {
+                    log_skip(relative_path, "outside --modified-within");
+                    continue;
+                }
+                modified_matched += 1;
+            }
+            if let Some(reference) = args.newer_than {
+                if modified.is_none_or(|m| m <= reference) {
+                    log_skip(relative_path, "not newer than --newer-than");
+                    continue;
+                }
+                newer_matched += 1;
+            }
+            if args.follow_links {
+                if let Ok(canonical) = fs::canonicalize(path) {
+                    if let Some(first) = link_targets.get(&canonical) {
+                        log_skip(relative_path, &format!("same file as {}", first.display()));
+                        repeated_targets += 1;
+                        continue;
+                    }
+                    link_targets.insert(canonical, relative_path.to_owned());
+                }
+            }
+        }
+
+        // --print0 and --dry-run list what's left after the limits below
+        // without reading anything, so the files go on with no content.
+        if args.print0 || args.dry_run {
+            listed_sizes.insert(relative_path.to_owned(), size);
+            collected.push(CollectedFile {
+                relative_path: relative_path.to_owned(),
+                display_path: relative_path.to_string_lossy().into_owned(),
+                extension,
+                language,
+                content: String::new(),
+                link_target: None,
+                modified,
+                unchanged: false,
+                notes: Vec::new(),
+                sha256: None,
+                todos: Vec::new(),
+            });
+            continue;
+        }
+
+        // The bytes kept and the full size of a file cut by --truncate-file-size.
+        let mut truncated: Option<(usize, u64)> = None;
+        let (mut content, link_target, modified) = match candidate.source {
+            FileSource::Disk(path) => {
+                let link_target = if args.follow_links {
+                    symlink_target(&path, relative_path, &base_dir)
+                } else {
+                    None
+                };
+
+                let read_started = Instant::now();
+                let truncate_at = args
+                    .truncate_file_size
+                    .zip(metadata.as_ref().map(fs::Metadata::len))
+                    .filter(|&(limit, size)| size > limit);
+                let cached = cache
+                    .as_ref()
+                    .zip(metadata.as_ref())
+                    .filter(|_| truncate_at.is_none())
+                    .and_then(|(cache, metadata)| cache.get(&path, metadata));
+                let read = match (cached, truncate_at) {
+                    (Some(read), _) => read,
+                    (None, Some((limit, size))) => match open_files.read_prefix(&path, limit) {
+                        Ok(bytes) => match utf8_prefix(bytes) {
+                            Some(content) => {
+                                truncated = Some((content.len(), size));
+                                CachedRead::Text(content)
+                            }
+                            None => CachedRead::Binary,
+                        },
+                        Err(e) => {
+                            eprintln!("Could not read file {:?}: {}", path, e);
+                            continue;
+                        }
+                    },
+                    (None, None) => {
+                        let read = match open_files.read_to_string(&path) {
+                            Ok(content) => CachedRead::Text(content),
+                            Err(e) if e.kind() == ErrorKind::InvalidData => CachedRead::Binary,
+                            Err(e) => {
+                                eprintln!("Could not read file {:?}: {}", path, e);
+                                continue;
+                            }
+                        };
+                        if let (Some(cache), Some(metadata)) = (&cache, &metadata) {
+                            cache.put(&path, metadata, &read);
+                        }
+                        read
+                    }
+                };
+                timings.reading += read_started.elapsed();
+
+                match read {
+                    CachedRead::Text(content) => {
+                        if args.show_encoding {
+                            note_encoding(relative_path, detect_encoding(content.as_bytes()));
+                        }
+                        (content, link_target, modified)
+                    }
+                    CachedRead::Binary => {
+                        if args.show_encoding {
+                            if let Ok(bytes) = fs::read(&path) {
+                                note_encoding(relative_path, detect_encoding(&bytes));
+                            }
+                        }
+                        log_binary_skip(relative_path);
+                        if args.show_binaries_in_tree {
+                            binary_paths.push(relative_path.to_owned());
+                        }
+                        continue;
+                    }
+                }
+            }
+            FileSource::Archive(mut data) => {
+                if args.show_encoding {
+                    note_encoding(relative_path, detect_encoding(&data));
+                }
+                let size = data.len() as u64;
+                let content = match args.truncate_file_size.filter(|&limit| size > limit) {
+                    Some(limit) => {
+                        data.truncate(limit as usize);
+                        utf8_prefix(data).inspect(|content| truncated = Some((content.len(), size)))
+                    }
+                    None => String::from_utf8(data).ok(),
+                };
+                match content {
+                    Some(content) => (content, None, None),
+                    None => {
+                        log_binary_skip(relative_path);
+                        if args.show_binaries_in_tree {
+                            binary_paths.push(relative_path.to_owned());
+                        }
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if let Some(max_lines) = args.max_file_lines {
+            let lines = content.lines().count();
+            if lines > max_lines {
+                log_skip(
+                    relative_path,
+                    &format!("{} lines, over --max-file-lines", lines),
+                );
+                excluded_by_lines += 1;
+                continue;
+            }
+        }
+
+        let unchanged = args.diff_against.as_ref().is_some_and(|baseline| {
+            open_files
+                .read_to_string(&Path::new(baseline).join(relative_path))
+                .is_ok_and(|baseline_content| baseline_content == content)
+        });
+
+        // --snapshot compares files as read, like --with-hash, so changing
+        // options such as --head doesn't mark them all changed.
+        let read_hash =
+            (args.with_hash || args.snapshot.is_some()).then(|| sha256_hex(content.as_bytes()));
+        if let (Some(hash), Some(_)) = (&read_hash, &args.snapshot) {
+            read_hashes.insert(relative_path.to_owned(), hash.clone());
+        }
+        let sha256 = read_hash.filter(|_| args.with_hash);
+
+        if args.eol_report {
+            if let Some(endings) = non_lf_line_endings(&content) {
+                non_lf_files.push(format!("{} ({})", relative_path.display(), endings));
+            }
+        }
+
+        if let Some(editorconfigs) = &mut editorconfigs {
+            let properties = editorconfigs.properties(&base_dir.join(relative_path));
+            if let Some(charset) = &properties.charset {
+                if let Some(problem) = editorconfig::charset_mismatch(charset, &content) {
+                    eprintln!("{:?} {} per .editorconfig", relative_path, problem);
+                }
+            }
+            if let Some(insert) = properties.insert_final_newline {
+                content = editorconfig::apply_final_newline(content, insert);
+            }
+        }
+
+        let mut notes = Vec::new();
+        if args.mark_no_final_newline && !content.is_empty() && !content.ends_with('\n') {
+            notes.push("no final newline".to_string());
+        }
+
+        content = strip_bom(content);
+
+        if args.exclude_generated_by_comment && looks_generated(&content) {
+            log_skip(relative_path, "generated-code comment");
+            excluded_generated_by_comment += 1;
+            continue;
+        }
+
+        if args.deterministic && content.contains('\r') {
+            content = content.replace("\r\n", "\n");
+        }
+
+        if args.validate_syntax || args.skip_invalid_syntax {
+            if let Some(Err(e)) = syntax::check(&extension, &content) {
+                invalid_syntax += 1;
+                if args.skip_invalid_syntax {
+                    log_noted_skip(relative_path, &format!("invalid syntax: {}", e));
+                    continue;
+                }
+                notice!("Invalid syntax in {:?}: {}", relative_path, e);
+            }
+        }
+
+        if args.fail_on_secret {
+            for secret in secrets::find_secrets(&content) {
+                secrets_found.push(format!(
+                    "{}:{}: {}",
+                    relative_path.display(),
+                    secret.line,
+                    secret.kind
+                ));
+            }
+        }
+
+        let todos = todo_markers
+            .as_ref()
+            .map_or_else(Vec::new, |markers| find_todos(&content, markers));
+
+        let ranges: Vec<&LineRange> = line_ranges
+            .iter()
+            .filter(|range| range.path == relative_path)
+            .collect();
+        if !ranges.is_empty() {
+            ranged_paths.insert(relative_path.to_owned());
+            content = select_line_ranges(
+                &content,
+                &ranges,
+                relative_path,
+                &get_comment_syntax(&language),
+            );
+        }
+
+        if let Some(changed) = changed_hunks.as_ref().and_then(|c| c.get(relative_path)) {
+            match hunks::enclosing_spans(&content, &extension, changed) {
+                Some(spans) => {
+                    let ranges: Vec<LineRange> = spans
+                        .into_iter()
+                        .map(|(start, end)| LineRange {
+                            path: relative_path.to_owned(),
+                            start,
+                            end,
+                        })
+                        .collect();
+                    content = select_line_ranges(
+                        &content,
+                        &ranges.iter().collect::<Vec<_>>(),
+                        relative_path,
+                        &get_comment_syntax(&language),
+                    );
+                }
+                None => notes.push("whole file: no function spans found".to_string()),
+            }
+        }
+
+        if let Some(command) = &args.content_filter {
+            match run_shell_filter(command, Some(relative_path), &content) {
+                Ok(filtered) => content = filtered,
+                Err(e) => eprintln!(
+                    "Content filter failed for {:?} ({}); keeping the original content",
+                    relative_path, e
+                ),
+            }
+        }
+
+        if args.strip_license_headers {
+            let stripped = strip_leading_license(&content, &get_comment_syntax(&language));
+            if stripped.len() != content.len() {
+                license_headers_stripped += 1;
+                content = stripped;
+            }
+        }
+
+        if args.exclude_minified && looks_minified(&content) {
+            log_skip(relative_path, "minified");
+            excluded_minified += 1;
+            continue;
+        }
+
+        if args.minify_json && extension == "json" {
+            match minify_json(&content) {
+                Ok(minified) => content = minified,
+                Err(e) => eprintln!("Could not minify JSON file {:?}: {}", relative_path, e),
+            }
+        }
+
+        if skip_content
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(&content))
+        {
+            log_skip(relative_path, "--skip-content match");
+            excluded_by_content += 1;
+            continue;
+        }
+
+        if let Some(regex) = &grep {
+            if !regex.is_match(&content) {
+                log_skip(relative_path, "no --grep match");
+                continue;
+            }
+            if let Some(n) = args.context_lines {
+                content = extract_context(&content, regex, n);
+            }
+        }
+
+        if args.collect_comments_only {
+            content = extract_comments(&content, &language);
+            if content.is_empty() {
+                log_skip(relative_path, "no comments");
+                continue;
+            }
+        }
+
+        if let Some(n) = args.head {
+            content = truncate_lines(&content, n, false, &get_comment_syntax(&language));
+        } else if let Some(n) = args.tail {
+            content = truncate_lines(&content, n, true, &get_comment_syntax(&language));
+        }
+
+        if let Some((shown, size)) = truncated {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(
+                &get_comment_syntax(&language)
+                    .comment(&format!("... truncated, {} of {} bytes shown", shown, size)),
+            );
+            content.push('\n');
+            verbose!(
+                "Truncated {:?} to {} of {} bytes",
+                relative_path,
+                shown,
+                size
+            );
+            truncated_files += 1;
+        }
+
+        if let Some(mode) = args.indent {
+            content = normalize_indent(&content, mode);
+        }
+
+        if let Some(max) = args.max_line_length {
+            content = limit_line_length(&content, max.get(), args.line_length_mode);
+        }
+
+        verbose!(
+            "+ collected {} ({})",
+            relative_path.display(),
+            format_size(content.len(), args.raw_bytes)
+        );
+        if unchanged {
+            folded += 1;
+        }
+        collected.push(CollectedFile {
+            relative_path: relative_path.to_owned(),
+            display_path: relative_path.to_string_lossy().into_owned(),
+            extension,
+            language,
+            content,
+            link_target,
+            modified,
+            unchanged,
+            notes,
+            sha256,
+            todos,
+        });
+    }
+
+    if !secrets_found.is_empty() {
+        eprintln!("Possible secrets found:");
+        for secret in &secrets_found {
+            eprintln!("    {}", secret);
+        }
+        return Err(format!(
+            "aborting because of --fail-on-secret ({} matches); nothing was copied",
+            secrets_found.len()
+        )
+        .into());
+    }
+
+    for range in &args.line_range {
+        if !ranged_paths.contains(&range.path) {
+            eprintln!(
+                "--line-range {:?} matched no collected file; paths are relative to the collected directory",
+                range.path
+            );
+        }
+    }
+
+    timings.lap("read and process");
+
+    if args.show_encoding {
+        let counts: Vec<String> = encodings
+            .iter()
+            .map(|(encoding, count)| format!("{} {}", count, encoding))
+            .collect();
+        summary!("Encodings read: {}.", counts.join(", "));
+    }
+
+    if args.eol_report {
+        non_lf_files.sort();
+        if non_lf_files.is_empty() {
+            summary!("All files read have LF line endings.");
+        } else {
+            summary!(
+                "{} files have non-LF line endings: {}.",
+                non_lf_files.len(),
+                non_lf_files.join(", ")
+            );
+        }
+    }
+
+    if args.exclude_tests {
+        summary!("Excluded {} test files.", excluded_tests);
+    }
+
+    if args.exclude_lockfiles {
+        summary!("Excluded {} lockfiles.", excluded_lockfiles);
+    }
+
+    if args.report_unsupported_extensions && !skipped_extensions.is_empty() {
+        let mut skipped: Vec<(String, usize)> = skipped_extensions.into_iter().collect();
+        skipped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut list: Vec<String> = skipped
+            .iter()
+            .take(UNSUPPORTED_EXTENSIONS_SHOWN)
+            .map(|(extension, count)| match extension.as_str() {
+                "" => format!("no extension ({})", count),
+                _ => format!("{} ({})", extension, count),
+            })
+            .collect();
+        if skipped.len() > UNSUPPORTED_EXTENSIONS_SHOWN {
+            list.push(format!(
+                "and {} more",
+                skipped.len() - UNSUPPORTED_EXTENSIONS_SHOWN
+            ));
+        }
+        summary!("Skipped extensions not in -e: {}.", list.join(", "));
+    }
+
+    if args.exclude_minified {
+        summary!("Excluded {} minified files.", excluded_minified);
+    }
+
+    if let Some(max_lines) = args.max_file_lines {
+        summary!(
+            "Skipped {} files over {} lines.",
+            excluded_by_lines,
+            max_lines
+        );
+    }
+
+    if let Some(pattern) = &args.skip_content {
+        summary!(
+            "Excluded {} files matching --skip-content {:?}.",
+            excluded_by_content,
+            pattern
+        );
+    }
+
+    if repeated_targets > 0 {
+        summary!(
+            "Skipped {} files already collected through another path.",
+            repeated_targets
+        );
+    }
+
+    if let Some(limit) = args.truncate_file_size {
+        summary!(
+            "Truncated {} files to their first {}.",
+            truncated_files,
+            format_size(limit as usize, args.raw_bytes)
+        );
+    }
+
+    if let Some(within) = args.modified_within {
+        summary!(
+            "{} files were modified in the last {}.",
+            modified_matched,
+            humantime::format_duration(within)
+        );
+    }
+
+    if let Some(reference) = args.newer_than {
+        summary!(
+            "{} files were modified after {}.",
+            newer_matched,
+            humantime::format_rfc3339_seconds(reference)
+        );
+    }
+
+    if let Some(baseline) = &args.diff_against {
+        summary!("Folded {} files unchanged from {}.", folded, baseline);
+    }
+
+    if args.strip_license_headers {
+        summary!("Stripped {} license headers.", license_headers_stripped);
+    }
+
+    if args.skip_invalid_syntax {
+        summary!("Excluded {} files with invalid syntax.", invalid_syntax);
+    } else if args.validate_syntax {
+        summary!("Found {} files with invalid syntax.", invalid_syntax);
+    }
+
+    if args.exclude_generated_by_comment {
+        summary!(
+            "Excluded {} files marked as generated by a comment.",
+            excluded_generated_by_comment
+        );
+    }
+
+    if excluded_generated > 0 {
+        summary!(
+            "Excluded {} generated or vendored files (see .gitattributes; use --include-generated to keep them).",
+            excluded_generated
+        );
+    }
+
+    // Sampling by directory or name needs a deterministic order, which walk order isn't.
+    let sampled = args.max_matches_per_dir.is_some() || args.max_per_basename.is_some();
+    let sort = match (args.sort, args.modified_within, sampled) {
+        _ if args.deterministic => Some(SortOrder::Path),
+        (None, Some(_), _) => Some(SortOrder::MtimeDesc),
+        (None, None, true) => Some(SortOrder::Path),
+        (sort, _, _) => sort,
+    };
+    match sort {
+        Some(SortOrder::Path) => collected
+            .sort_by(|a, b| compare_paths(&a.relative_path, &b.relative_path, args.tree_sort)),
+        Some(SortOrder::MtimeDesc) => collected.sort_by(|a, b| {
+            b.modified
+                .cmp(&a.modified)
+                .then_with(|| a.relative_path.cmp(&b.relative_path))
+        }),
+        None => {}
+    }
+
+    if args.readme_first || args.config_first {
+        // A stable sort keeps the chosen order among READMEs (or configs) of
+        // equal depth and among all other files.
+        collected.sort_by_key(|file| {
+            let depth = file.relative_path.components().count();
+            if args.readme_first && is_readme(&file.relative_path) {
+                (0, depth)
+            } else if args.config_first && is_project_config(&file.relative_path) {
+                (1, depth)
+            } else {
+                (2, 0)
+            }
+        });
+    }
+
+    if let Some(order_path) = &args.order_file {
+        let order = parse_path_list(&fs::read_to_string(order_path)?);
+        let mut remaining: Vec<Option<CollectedFile>> = collected.into_iter().map(Some).collect();
+        let mut ordered = Vec::new();
+
+        for listed in &order {
+            let slot = remaining
+                .iter_mut()
+                .find(|slot| slot.as_ref().is_some_and(|f| f.relative_path == *listed));
+            match slot.and_then(Option::take) {
+                Some(file) => ordered.push(file),
+                None => eprintln!("File {:?} from {} was not collected", listed, order_path),
+            }
+        }
+
+        for file in remaining.into_iter().flatten() {
+            if args.strict_order {
+                log_skip(&file.relative_path, "not in --order-file");
+            } else {
+                ordered.push(file);
+            }
+        }
+        collected = ordered;
+    }
+
+    if let Some(max_per_dir) = args.max_matches_per_dir {
+        let mut per_dir: HashMap<PathBuf, usize> = HashMap::new();
+        for file in &collected {
+            let dir = file
+                .relative_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_owned();
+            *per_dir.entry(dir).or_default() += 1;
+        }
+
+        let mut kept: HashMap<PathBuf, usize> = HashMap::new();
+        collected.retain(|file| {
+            let dir = file.relative_path.parent().unwrap_or(Path::new(""));
+            let count = kept.entry(dir.to_owned()).or_default();
+            *count += 1;
+            if *count > max_per_dir {
+                log_skip(&file.relative_path, "--max-matches-per-dir limit");
+            }
+            *count <= max_per_dir
+        });
+
+        let mut sampled: Vec<(PathBuf, usize)> = per_dir
+            .into_iter()
+            .filter(|(_, total)| *total > max_per_dir)
+            .collect();
+        sampled.sort();
+        for (dir, total) in sampled {
+            summary!(
+                "Sampled {} of {} in {}/",
+                max_per_dir,
+                total,
+                if dir.as_os_str().is_empty() {
+                    ".".into()
+                } else {
+                    dir.to_string_lossy()
+                }
+            );
+        }
+    }
+
+    if let Some(max_per_name) = args.max_per_basename {
+        let name_of = |file: &CollectedFile| {
+            file.relative_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        collected.retain(|file| {
+            let count = seen.entry(name_of(file)).or_default();
+            *count += 1;
+            if *count > max_per_name {
+                log_skip(&file.relative_path, "--max-per-basename limit");
+            }
+            *count <= max_per_name
+        });
+        let mut capped: Vec<(String, usize)> = seen
+            .into_iter()
+            .filter(|(_, count)| *count > max_per_name)
+            .collect();
+        capped.sort();
+        for (name, count) in capped {
+            summary!(
+                "Kept {} of {} files named {} (skipped {}).",
+                max_per_name,
+                count,
+                name,
+                count - max_per_name
+            );
+        }
+    }
+
+    // Nothing is read for --print0 and --dry-run, so there is no content to compare.
+    let listing = args.print0 || args.dry_run;
+    if let Some(strategy) = args
+        .dedupe_by
+        .filter(|strategy| !(listing && matches!(strategy, DedupeBy::Content)))
+    {
+        let key = |file: &CollectedFile| -> String {
+            match strategy {
+                DedupeBy::Content if args.dedupe_ignore_whitespace => {
+                    let stripped: String = file
+                        .content
+                        .chars()
+                        .filter(|c| !c.is_whitespace())
+                        .collect();
+                    sha256_hex(stripped.as_bytes())
+                }
+                DedupeBy::Content => sha256_hex(file.content.as_bytes()),
+                DedupeBy::Filename => file
+                    .relative_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                DedupeBy::PathSuffix => {
+                    let components: Vec<_> = file.relative_path.components().collect();
+                    let start = components.len().saturating_sub(args.dedupe_suffix_len);
+                    components[start..]
+                        .iter()
+                        .collect::<PathBuf>()
+                        .to_string_lossy()
+                        .into_owned()
+                }
+            }
+        };
+
+        // The first file in --sort order wins. With --dedupe-ignore-whitespace
+        // the exact content's hash tells whitespace-only duplicates apart.
+        let exact_hash = |file: &CollectedFile| {
+            args.dedupe_ignore_whitespace
+                .then(|| sha256_hex(file.content.as_bytes()))
+        };
+        let mut kept: HashMap<String, (PathBuf, Option<String>)> = HashMap::new();
+        let mut dropped = 0;
+        collected.retain(|file| match kept.get(&key(file)) {
+            Some((original, original_hash)) => {
+                let kind = if *original_hash != exact_hash(file) {
+                    "differs only in whitespace from"
+                } else {
+                    "duplicate of"
+                };
+                summary!(
+                    "Dropped {} ({} {}).",
+                    file.relative_path.display(),
+                    kind,
+                    original.display()
+                );
+                explain!(
+                    file.relative_path.display(),
+                    "excluded ({} {})",
+                    kind,
+                    original.display()
+                );
+                dropped += 1;
+                false
+            }
+            None => {
+                kept.insert(key(file), (file.relative_path.clone(), exact_hash(file)));
+                true
+            }
+        });
+        if dropped > 0 {
+            summary!("Dropped {} duplicate files.", dropped);
+        }
+    }
+
+    if !args.max_bytes_per_ext.is_empty() {
+        let budgets: HashMap<&str, u64> = args
+            .max_bytes_per_ext
+            .iter()
+            .map(|(ext, budget)| (ext.as_str(), *budget))
+            .collect();
+        // Per extension: bytes kept, files kept, files dropped.
+        let mut used: HashMap<String, (u64, usize, usize)> = HashMap::new();
+        collected.retain(|file| {
+            let Some(&budget) = budgets.get(file.extension.as_str()) else {
+                return true;
+            };
+            let (bytes, kept, dropped) = used.entry(file.extension.clone()).or_default();
+            let size = file.content.len() as u64;
+            if *dropped == 0 && *bytes + size <= budget {
+                *bytes += size;
+                *kept += 1;
+                return true;
+            }
+            *dropped += 1;
+            log_skip(&file.relative_path, "--max-bytes-per-ext budget");
+            false
+        });
+        let mut truncated: Vec<_> = used.into_iter().filter(|(_, u)| u.2 > 0).collect();
+        truncated.sort();
+        for (extension, (bytes, kept, dropped)) in truncated {
+            summary!(
+                "Kept {} of {} .{} files ({}) to stay within its {} budget.",
+                kept,
+                kept + dropped,
+                extension,
+                format_size(bytes as usize, args.raw_bytes),
+                format_size(budgets[extension.as_str()] as usize, args.raw_bytes)
+            );
+        }
+    }
+
+    if !args.max_files_ext.is_empty() {
+        let caps: HashMap<&str, usize> = args
+            .max_files_ext
+            .iter()
+            .map(|(ext, max)| (ext.as_str(), *max))
+            .collect();
+        // Per extension: files seen so far.
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        collected.retain(|file| {
+            let Some(&max) = caps.get(file.extension.as_str()) else {
+                return true;
+            };
+            let count = seen.entry(file.extension.clone()).or_default();
+            *count += 1;
+            if *count > max {
+                log_skip(&file.relative_path, "--max-files-ext limit");
+            }
+            *count <= max
+        });
+        let mut capped: Vec<(String, usize)> = seen
+            .into_iter()
+            .filter(|(ext, count)| *count > caps[ext.as_str()])
+            .collect();
+        capped.sort();
+        for (extension, count) in capped {
+            summary!(
+                "Kept {} of {} .{} files (skipped {}).",
+                caps[extension.as_str()],
+                count,
+                extension,
+                count - caps[extension.as_str()]
+            );
+        }
+    }
+
+    if let Some(max_files) = args.max_files {
+        if collected.len() > max_files {
+            summary!(
+                "Keeping the first {} of {} files.",
+                max_files,
+                collected.len()
+            );
+            for file in &collected[max_files..] {
+                log_skip(&file.relative_path, "--max-files limit");
+            }
+            collected.truncate(max_files);
+        }
+    }
+
+    if listing {
+        for file in &collected {
+            explain!(
+                file.relative_path.display(),
+                "{}",
+                inclusion(&file.extension)
+            );
+        }
+        print_listing(args, &collected, &listed_sizes)?;
+        return Ok(collected.len());
+    }
+
+    if args.relative_from_cwd {
+        let cwd = normalize_path(&std::env::current_dir()?);
+        for file in &mut collected {
+            let absolute =
+                normalize_path(&std::path::absolute(base_dir.join(&file.relative_path))?);
+            let display = relative_path_from(&absolute, &cwd).unwrap_or(absolute);
+            file.display_path = display.to_string_lossy().into_owned();
+        }
+    }
+
+    if args.relative_to_git_root {
+        match git_output(&base_dir, &["rev-parse", "--show-toplevel"]) {
+            Some(root) => {
+                // git reports the root with symlinks resolved, so resolve ours too.
+                let root = normalize_path(&fs::canonicalize(root)?);
+                let base = normalize_path(&fs::canonicalize(&base_dir)?);
+                for file in &mut collected {
+                    let absolute = base.join(&file.relative_path);
+                    if let Some(display) = relative_path_from(&absolute, &root) {
+                        file.display_path = display.to_string_lossy().into_owned();
+                    }
+                }
+            }
+            None => eprintln!(
+                "{} is not inside a git repository; header paths stay relative to it",
+                base_dir.display()
+            ),
+        }
+    }
+
+    // With a single file the whole directory would be "common", leaving
+    // nothing but the file name, so only strip when there are several.
+    if args.strip_prefix_common && collected.len() > 1 {
+        let prefix = common_dir_prefix(collected.iter().map(|f| Path::new(&f.display_path)));
+        if !prefix.as_os_str().is_empty() {
+            for file in &mut collected {
+                if let Ok(stripped) = Path::new(&file.display_path).strip_prefix(&prefix) {
+                    file.display_path = stripped.to_string_lossy().into_owned();
+                }
+            }
+            summary!("Stripped common prefix {} from headers.", prefix.display());
+        }
+    }
+
+    if let Some(max_total_lines) = args.max_total_lines {
+        let mut total_lines = 0;
+        let mut keep = collected.len();
+        for (i, file) in collected.iter().enumerate() {
+            let lines = file.content.lines().count();
+            if total_lines + lines > max_total_lines {
+                keep = i;
+                break;
+            }
+            total_lines += lines;
+        }
+        if keep < collected.len() {
+            summary!(
+                "Stopped after {} of {} files ({} lines) to stay within {} total lines.",
+                keep,
+                collected.len(),
+                total_lines,
+                max_total_lines
+            );
+            for file in &collected[keep..] {
+                log_skip(&file.relative_path, "--max-total-lines limit");
+            }
+            collected.truncate(keep);
+        }
+    }
 
-#[derive(Parser)]
-#[command(
-    name = "code_collector",
-    about = "Collect code files into a buffer, respecting .gitignore and filtering by extension"
-)]
-struct Cli {
-    /// The directory to process
-    directory: String,
+    if let Some(max_total_tokens) = args.max_total_tokens {
+        let mut total_tokens = 0;
+        let mut keep = collected.len();
+        for (i, file) in collected.iter().enumerate() {
+            let tokens = count_tokens(&file.content);
+            if total_tokens + tokens > max_total_tokens {
+                keep = i;
+                break;
+            }
+            total_tokens += tokens;
+        }
+        if keep < collected.len() {
+            summary!(
+                "Stopped after {} of {} files ({}) to stay within {} total tokens.",
+                keep,
+                collected.len(),
+                describe_tokens(total_tokens),
+                max_total_tokens
+            );
+            for file in &collected[keep..] {
+                log_skip(&file.relative_path, "--max-total-tokens limit");
+            }
+            collected.truncate(keep);
+        }
+    }
 
-    /// File extensions to include (e.g., rs, py). Specify multiple times for multiple extensions.
-    #[arg(short, long, value_name = "EXTENSION", use_value_delimiter = true)]
-    extensions: Vec<String>,
+    if let Some(limit) = args.fit_clipboard {
+        let sizes: Vec<u64> = collected.iter().map(buffer_share).collect();
+        let dropped = files_to_drop(&collected, &sizes, limit, args.fit_drop);
+        if !dropped.is_empty() {
+            let dropped_bytes =
+                drop_files(&mut collected, &dropped, "--fit-clipboard", args.raw_bytes);
+            summary!(
+                "Dropped {} files ({}) to fit --fit-clipboard {}.",
+                dropped.len(),
+                format_size(dropped_bytes as usize, args.raw_bytes),
+                format_size(limit as usize, args.raw_bytes)
+            );
+        }
+    }
 
-    /// Directories to exclude
-    #[arg(
-        short = 'x',
-        long,
-        value_name = "DIRECTORY",
-        use_value_delimiter = true
-    )]
-    exclude_dirs: Vec<String>,
-}
+    if let Some(budget) = args.interactive_budget {
+        let tokens: Vec<u64> = collected.iter().map(token_share).collect();
+        if tokens.iter().sum::<u64>() > budget {
+            let dropped = if io::stdin().is_terminal() {
+                pick_files_to_drop(&collected, &tokens, budget)?
+            } else {
+                notice!("stdin isn't a terminal, so the largest files are dropped to fit --interactive-budget");
+                files_to_drop(&collected, &tokens, budget, FitDrop::Largest)
+            };
+            if !dropped.is_empty() {
+                let dropped_tokens = dropped.iter().map(|&i| tokens[i]).sum::<u64>() as usize;
+                drop_files(
+                    &mut collected,
+                    &dropped,
+                    "--interactive-budget",
+                    args.raw_bytes,
+                );
+                summary!(
+                    "Dropped {} files ({}) to fit --interactive-budget {}.",
+                    dropped.len(),
+                    describe_tokens(dropped_tokens),
+                    budget
+                );
+            }
+        }
+    }
 
-enum CommentStyle {
-    Line(&'static str),
-    Block(&'static str, &'static str),
-}
+    if let Some(format) = args.manifest_only {
+        print_manifest(&collected, format)?;
+        if let Some(path) = &args.summary_to_file {
+            let source = args.archive.as_deref().unwrap_or(&directory);
+            write_summary_file(path, args, source, &collected)?;
+        }
+        return Ok(collected.len());
+    }
 
-fn get_comment_syntax(extension: &str) -> CommentStyle {
-    match extension {
-        "rs" | "js" | "ts" | "c" | "h" | "cpp" | "hpp" | "java" | "cs" | "go" | "swift" | "kt"
-        | "kts" => CommentStyle::Line("//"),
-        "py" | "sh" | "yaml" | "yml" | "toml" | "ini" | "rb" | "pl" | "r" | "php" | "ps1"
-        | "makefile" => CommentStyle::Line("#"),
-        "html" | "xml" | "xhtml" => CommentStyle::Block("<!--", "-->"),
-        "css" => CommentStyle::Block("/*", "*/"),
-        _ => CommentStyle::Line("//"),
+    if collected.is_empty() {
+        let source = args.archive.as_deref().unwrap_or(&directory);
+        notice!("No files matched your filters in {}.", source);
+        if args.emit_empty_tree && log::enabled(Verbosity::Normal) {
+            let mut root = TreeNode::new(String::new());
+            for dir in &walked_dirs {
+                let components: Vec<String> = dir
+                    .components()
+                    .map(|comp| format!("{}/", comp.as_os_str().to_string_lossy()))
+                    .collect();
+                root.add_directory(&components);
+            }
+            let tree_options = TreeOptions {
+                file_counts: false,
+                style: args.tree_style,
+                sort: args.tree_sort,
+                max_entries: args.max_tree_entries.map(NonZeroUsize::get),
+                max_depth: args.tree_depth.map(NonZeroUsize::get),
+                colored: true,
+                max_width: args.tree_width.map(NonZeroUsize::get),
+            };
+            log::progress!("Walked directories:");
+            for line in root.render(&tree_options).lines() {
+                log::progress!("{}", line);
+            }
+        }
+        if let Some(path) = &args.summary_to_file {
+            write_summary_file(path, args, source, &collected)?;
+        }
+        return Ok(0);
     }
-}
 
-struct TreeNode {
-    name: String,
-    children: HashMap<String, TreeNode>,
-}
+    for file in &collected {
+        explain!(
+            file.relative_path.display(),
+            "{}",
+            inclusion(&file.extension)
+        );
+    }
 
-impl TreeNode {
-    fn new(name: String) -> Self {
-        TreeNode {
-            name,
-            children: HashMap::new(),
+    if args.group_by_extension {
+        // Stable, so files keep their --sort order within each group.
+        collected.sort_by(|a, b| {
+            (a.extension.is_empty(), &a.extension).cmp(&(b.extension.is_empty(), &b.extension))
+        });
+    }
+    let tests_from = if args.tests_separate {
+        collected.sort_by_key(|file| looks_like_test(&file.relative_path));
+        collected
+            .iter()
+            .position(|file| looks_like_test(&file.relative_path))
+    } else {
+        None
+    };
+
+    if !renames.is_empty() {
+        let mut replacements = 0;
+        for file in &mut collected {
+            let path = file.relative_path.to_string_lossy();
+            file.relative_path = PathBuf::from(apply_renames(&path, &renames, &mut 0));
+            file.display_path = apply_renames(&file.display_path, &renames, &mut replacements);
+            file.content = apply_renames(&file.content, &renames, &mut replacements);
         }
+        summary!("Made {} replacements from the rename map.", replacements);
     }
 
-    fn add_path(&mut self, path_components: &[String]) {
-        if path_components.is_empty() {
-            return;
+    // The tree already joins components with `/`, so only the headers need this.
+    if args.path_style == PathStyle::Posix && std::path::MAIN_SEPARATOR != '/' {
+        for file in &mut collected {
+            file.display_path = file.display_path.replace(std::path::MAIN_SEPARATOR, "/");
+        }
+    }
+    for file in &mut collected {
+        file.display_path = transform_path(&file.display_path, &args.path_transform);
+    }
+    if args.rust_module_headers {
+        // Collecting `src` itself leaves the paths relative to it.
+        let in_src = args.archive.is_none()
+            && fs::canonicalize(&base_dir)
+                .is_ok_and(|dir| dir.file_name().is_some_and(|name| name == "src"));
+        for file in &mut collected {
+            if let Some(module) = rust_module_path(&file.relative_path, in_src) {
+                file.display_path = module;
+            }
         }
-        let name = path_components[0].clone();
-        let node = self
-            .children
-            .entry(name.clone())
-            .or_insert_with(|| TreeNode::new(name));
-        node.add_path(&path_components[1..]);
     }
+    // The paths the tree shows, which --path-transform rewrites too.
+    let tree_paths: Vec<PathBuf> = collected
+        .iter()
+        .map(|file| {
+            let path = file.relative_path.to_string_lossy();
+            PathBuf::from(transform_path(&path, &args.path_transform))
+        })
+        .collect();
 
-    fn print(&self, prefix: &str, is_last: bool) {
-        if !self.name.is_empty() {
-            println!(
-                "{}{}{}",
-                prefix,
-                if is_last { "└── " } else { "├── " },
-                self.name
-            );
+    if let Some(snapshot) = &args.snapshot {
+        update_snapshot(Path::new(snapshot), &collected, &read_hashes)
+            .map_err(|e| format!("could not update --snapshot {}: {}", snapshot, e))?;
+    }
+
+    let root_name = if args.relative_tree_root || args.deterministic {
+        let source = match &args.archive {
+            Some(archive_path) => PathBuf::from(archive_path),
+            None => fs::canonicalize(&base_dir).unwrap_or_else(|_| base_dir.clone()),
+        };
+        source
+            .file_name()
+            .map(|name| format!("{}/", name.to_string_lossy()))
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let mut root = TreeNode::new(root_name.clone());
+
+    for path in &tree_paths {
+        let components: Vec<String> = path
+            .components()
+            .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        root.add_path(&components);
+    }
+
+    for path in &binary_paths {
+        let path = PathBuf::from(transform_path(
+            &path.to_string_lossy(),
+            &args.path_transform,
+        ));
+        let components: Vec<String> = path
+            .components()
+            .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        root.add_uncollected_path(&components, Uncollected::Binary);
+    }
+
+    if let Some(seed_path) = &args.seed_tree_from {
+        for path in parse_path_list(&fs::read_to_string(seed_path)?) {
+            let path = PathBuf::from(transform_path(
+                &path.to_string_lossy(),
+                &args.path_transform,
+            ));
+            let components: Vec<String> = path
+                .components()
+                .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            root.add_uncollected_path(&components, Uncollected::Listed);
+        }
+    }
+
+    if args.collapse_single_child_dirs {
+        root.collapse_single_child_dirs();
+    }
+    if let Some(path) = &args.tree_json {
+        write_tree_json(path, &root, args.tree_sort)?;
+    }
+
+    let (tree, display_tree) = if args.compact_tree {
+        let max_entries = args.max_tree_entries.map(NonZeroUsize::get);
+        (
+            compact_tree(&tree_paths, args.tree_sort, None),
+            compact_tree(&tree_paths, args.tree_sort, max_entries),
+        )
+    } else {
+        let mut tree_options = TreeOptions {
+            file_counts: args.tree_file_counts,
+            style: args.tree_style,
+            sort: args.tree_sort,
+            max_entries: None,
+            max_depth: args.tree_depth.map(NonZeroUsize::get),
+            colored: false,
+            max_width: None,
+        };
+        let tree = root.render(&tree_options);
+        // The buffer gets the full plain tree; the terminal may get a colored,
+        // shortened one.
+        tree_options.colored = true;
+        tree_options.max_entries = args.max_tree_entries.map(NonZeroUsize::get);
+        tree_options.max_width = args.tree_width.map(NonZeroUsize::get);
+        (tree, root.render(&tree_options))
+    };
+
+    let sections: Option<Vec<DirSection>> = args.interleave.then(|| {
+        let mut by_dir: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+        for (i, path) in tree_paths.iter().enumerate() {
+            let dir = path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .components()
+                .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            by_dir.entry(dir).or_default().push(i);
+        }
+        let options = TreeOptions {
+            style: args.tree_style,
+            sort: args.tree_sort,
+            ..TreeOptions::default()
+        };
+        root.directory_fragments(&options)
+            .into_iter()
+            .map(|(dir, fragment)| DirSection {
+                fragment,
+                files: by_dir.remove(&dir).unwrap_or_default(),
+            })
+            .collect()
+    });
+
+    let mut summary = Vec::new();
+    if args.prepend_gitinfo {
+        match git_info(&base_dir) {
+            Some(info) => summary.push(SummaryBlock::Lines(None, info)),
+            None => notice!("Not inside a git repository; skipping --prepend-gitinfo."),
+        }
+    }
+    if args.include_ai_rules {
+        match ai_rules(&base_dir) {
+            Some(rules) => summary.extend(rules),
+            None => notice!("No AI rules files found; skipping --include-ai-rules."),
+        }
+    }
+    if args.with_summary_header {
+        // The path given can differ between machines; the directory's name doesn't.
+        let source = match root_name.strip_suffix('/') {
+            Some(name) if args.deterministic => name,
+            _ => args.archive.as_deref().unwrap_or(&directory),
+        };
+        summary.push(SummaryBlock::Lines(
+            None,
+            summary_header(source, &collected, !args.deterministic),
+        ));
+    }
+    if args.import_graph {
+        summary.push(SummaryBlock::Lines(
+            Some("Dependencies:"),
+            import_graph(&collected, ""),
+        ));
+    }
+    if args.todos_in_buffer {
+        summary.push(SummaryBlock::Lines(
+            Some("TODOs:"),
+            todo_list(&collected, ""),
+        ));
+    }
+    if args.toc {
+        summary.push(SummaryBlock::Lines(
+            Some("Contents:"),
+            table_of_contents(&collected, ""),
+        ));
+    }
+
+    let format_options = FormatOptions {
+        group_banners: args.group_by_extension,
+        footers: args.with_footer,
+        flatten_max_lines: args
+            .flatten_single_line_files
+            .then_some(args.flatten_max_lines.get()),
+        header_space: !args.no_header_space,
+        dir_banners: args.merge_adjacent_blank_headers,
+        header_templates: args.header_template_ext.iter().cloned().collect(),
+        tests_from,
+    };
+
+    if let Some(path) = &args.docstrings_output {
+        let mut docs = String::new();
+        let mut documented = 0;
+        for file in &collected {
+            let file_docs = extract_docstrings(&file.content, &file.language);
+            if file_docs.is_empty() {
+                continue;
+            }
+            documented += 1;
+            docs.push_str(&get_comment_syntax(&file.language).comment(&file.display_path));
+            docs.push('\n');
+            docs.push_str(&file_docs);
+            docs.push('\n');
+        }
+        fs::write(path, &docs)
+            .map_err(|e| format!("could not write --docstrings-output {}: {}", path, e))?;
+        summary!(
+            "Wrote the doc comments of {} files to {}.",
+            documented,
+            path
+        );
+    }
+
+    timings.lap("format");
+
+    let part_limit = match (args.split_output, args.chunk_tokens) {
+        (Some(max_bytes), _) => Some(PartLimit::Bytes(max_bytes)),
+        (None, Some(max_tokens)) => Some(PartLimit::Tokens(max_tokens.get())),
+        (None, None) => None,
+    };
+    if let (Some(output_dir), Some(limit)) = (&args.output_dir, part_limit) {
+        let formatted: Vec<String> = format_files(&collected, &format_options).collect();
+        let mut head = render_summary(&summary, OutputFormat::Comments);
+        if let Some(title) = &args.title {
+            head.insert_str(0, &title_banner(title, OutputFormat::Comments));
+        }
+        let (mut parts, placement) =
+            split_into_parts(&collected, &formatted, &head, limit, args.raw_bytes);
+        if args.no_trailing_separator {
+            parts.iter_mut().for_each(trim_trailing_newlines);
+        }
+        if let Some(tag) = &args.wrap_in_tag {
+            parts.iter_mut().for_each(|part| wrap_in_tag(tag, part));
+        }
+        fs::create_dir_all(output_dir)?;
+        for (i, part) in parts.iter().enumerate() {
+            fs::write(Path::new(output_dir).join(part_file_name(i)), part)?;
+            if let PartLimit::Tokens(_) = limit {
+                summary!(
+                    "{}: {}",
+                    part_file_name(i),
+                    describe_tokens(count_tokens(part))
+                );
+            }
+        }
+        // Maps each collected file to the part holding it, so the split can be traced back.
+        let index: Vec<serde_json::Value> = collected
+            .iter()
+            .zip(&placement)
+            .map(|(file, &part)| {
+                serde_json::json!({
+                    "path": file.relative_path.to_string_lossy(),
+                    "part": part_file_name(part),
+                    "bytes": file.content.len(),
+                    "extension": file.extension,
+                })
+            })
+            .collect();
+        fs::write(
+            Path::new(output_dir).join("index.json"),
+            serde_json::to_string_pretty(&index)? + "\n",
+        )?;
+
+        print_tree(&display_tree);
+        if args.summary_table {
+            print_summary_table(&collected, args.raw_bytes);
+        }
+        if args.highlight_todos {
+            print_todos(&collected);
+        }
+        timings.lap("write parts");
+        log::flush_summary();
+        info!(
+            "Split the code buffer ({}) into {} parts in {}.",
+            format_size(parts.iter().map(String::len).sum(), args.raw_bytes),
+            parts.len(),
+            output_dir
+        );
+        if args.timing {
+            timings.report();
         }
+        if let Some(path) = &args.summary_to_file {
+            let source = args.archive.as_deref().unwrap_or(&directory);
+            write_summary_file(path, args, source, &collected)?;
+        }
+        return Ok(collected.len());
+    }
 
-        let mut keys: Vec<&String> = self.children.keys().collect();
-        keys.sort();
-        for (i, key) in keys.iter().enumerate() {
-            let child = self.children.get(*key).unwrap();
-            let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-            child.print(&new_prefix, i == keys.len() - 1);
+    let write_buffer =
+        |format: OutputFormat, out: &mut dyn Write| -> Result<(), Box<dyn std::error::Error>> {
+            if let (Some(title), None) = (&args.title, &args.template_file) {
+                out.write_all(title_banner(title, format).as_bytes())?;
+            }
+            out.write_all(render_summary(&summary, format).as_bytes())?;
+            let rest = match (&args.template_file, format) {
+                (Some(template_path), _) => {
+                    render_template(template_path, &collected, &tree, args.title.as_deref())?
+                }
+                (None, OutputFormat::Comments) if args.prefix_lines => {
+                    format_prefixed_lines(&collected)
+                }
+                (None, OutputFormat::Comments) => match &sections {
+                    Some(sections) => {
+                        let formatted: Vec<String> =
+                            format_files(&collected, &format_options).collect();
+                        format_interleaved(sections, &formatted)
+                    }
+                    None => {
+                        for formatted in format_files(&collected, &format_options) {
+                            out.write_all(formatted.as_bytes())?;
+                        }
+                        String::new()
+                    }
+                },
+                (None, OutputFormat::Markdown) => match &sections {
+                    Some(sections) => format_markdown_interleaved(sections, &collected),
+                    None => format_markdown(&collected, &tree, tests_from, args.group_by_extension),
+                },
+                (None, OutputFormat::Repomix) => format_repomix(&collected, &tree),
+                (None, OutputFormat::Xml) => render_xml(
+                    &collected,
+                    &tree,
+                    args.title.as_deref(),
+                    &render_summary(&summary, OutputFormat::Repomix),
+                )?,
+                (None, OutputFormat::Json) => format_json(
+                    &collected,
+                    &tree,
+                    args.title.as_deref(),
+                    &render_summary(&summary, OutputFormat::Repomix),
+                ),
+            };
+            out.write_all(rest.as_bytes())?;
+            Ok(())
+        };
+    let render = |format: OutputFormat| -> Result<String, Box<dyn std::error::Error>> {
+        let mut buffer = Vec::new();
+        write_buffer(format, &mut buffer)?;
+        let mut buffer = String::from_utf8(buffer)?;
+        if args.no_trailing_separator {
+            trim_trailing_newlines(&mut buffer);
         }
+        if let Some(tag) = &args.wrap_in_tag {
+            wrap_in_tag(tag, &mut buffer);
+        }
+        post_process(args, buffer)
+    };
+    // Output files are written file by file, without the whole buffer in
+    // memory, unless it has to be processed as a whole first.
+    let stream = (args.post_process.is_none() && !args.no_trailing_separator)
+        .then_some(&write_buffer as &WriteBuffer);
+    print_tree(&display_tree);
+    if args.summary_table {
+        print_summary_table(&collected, args.raw_bytes);
+    }
+    if args.highlight_todos {
+        print_todos(&collected);
+    }
+
+    deliver(args, &render, stream, &tree, &mut timings)?;
+    if let Some(path) = &args.summary_to_file {
+        let source = args.archive.as_deref().unwrap_or(&directory);
+        write_summary_file(path, args, source, &collected)?;
     }
+    Ok(collected.len())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
+/// Wall-clock time spent in each phase of a run, for `--timing`.
+struct Timings {
+    started: Instant,
+    lap_started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    /// Time spent reading files, part of the `read and process` phase.
+    reading: Duration,
+}
 
-    let directory = args.directory;
-    println!("Processing directory: {}", directory);
+impl Timings {
+    fn new() -> Self {
+        let now = Instant::now();
+        Timings {
+            started: now,
+            lap_started: now,
+            phases: Vec::new(),
+            reading: Duration::ZERO,
+        }
+    }
 
-    let extensions: Vec<String> = args.extensions.iter().map(|s| s.to_lowercase()).collect();
+    /// Ends the current phase, naming it `name`.
+    fn lap(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.lap_started));
+        self.lap_started = now;
+    }
 
-    let mut types_builder = TypesBuilder::new();
+    /// Prints the breakdown to stderr.
+    fn report(&self) {
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| match *name {
+                "read and process" => {
+                    format!("{} {:.1?} (reading {:.1?})", name, duration, self.reading)
+                }
+                _ => format!("{} {:.1?}", name, duration),
+            })
+            .collect();
+        eprintln!(
+            "Timing: {}; total {:.1?}",
+            phases.join(", "),
+            self.started.elapsed()
+        );
+    }
+}
 
-    if !extensions.is_empty() {
-        for ext in &extensions {
-            let pattern = format!("*.{}", ext);
-            types_builder.add(ext, &pattern)?;
-            types_builder.select(ext);
+/// Pipes the finished buffer through `--post-process`, if given. On failure the
+/// unprocessed buffer is kept, unless `--strict` is set.
+fn post_process(args: &Cli, buffer: String) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(command) = &args.post_process else {
+        return Ok(buffer);
+    };
+    match run_shell_filter(command, None, &buffer) {
+        Ok(processed) => Ok(processed),
+        Err(e) if args.strict => Err(format!("--post-process failed: {}", e).into()),
+        Err(e) => {
+            eprintln!(
+                "--post-process failed ({}); keeping the unprocessed buffer",
+                e
+            );
+            Ok(buffer)
         }
+    }
+}
+
+/// Copies the buffer to the clipboard, as HTML or in chunks if asked, and
+/// returns the number of parts copied.
+fn copy_to_clipboard(
+    args: &Cli,
+    code_buffer: String,
+    tree: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut clipboard = SystemClipboard::open(args.clipboard_backend)?;
+    if args.html {
+        let html = format!(
+            "<pre>{}</pre>\n<pre>{}</pre>\n",
+            escape_html(tree),
+            escape_html(&code_buffer)
+        );
+        // Rich targets take the HTML; everything else gets the plain buffer.
+        if let Err(e) = clipboard.set_html(&html, &code_buffer) {
+            eprintln!(
+                "Could not copy HTML to the clipboard ({}); copying plain text instead",
+                e
+            );
+            clipboard.set_text(&code_buffer)?;
+        }
+    } else if let Some(max_bytes) = args.clipboard_chunks {
+        let chunks = split_into_chunks(&code_buffer, max_bytes.get());
+        copy_in_chunks(&mut clipboard, &chunks)?;
+        return Ok(chunks.len());
     } else {
-        types_builder.add_defaults();
+        clipboard.set_text(&code_buffer)?;
     }
+    Ok(1)
+}
 
-    let types_matcher = types_builder.build()?;
+/// Writes the buffer in `format` to `out` piece by piece.
+type WriteBuffer<'a> =
+    dyn Fn(OutputFormat, &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> + 'a;
 
-    let mut code_buffer = String::new();
-    let mut copied_files = Vec::new();
-
-    let mut excluded_dirs: HashSet<String> = [
-        "node_modules",
-        "target",
-        "build",
-        "dist",
-        "venv",
-        "env",
-        ".venv",
-        ".env",
-    ]
-    .iter()
-    .map(|s| s.to_string())
-    .collect();
+/// Counts the bytes written through it, to report the size of a streamed buffer.
+struct CountingWriter<W> {
+    inner: W,
+    written: usize,
+    last_byte: Option<u8>,
+}
 
-    // Include user-specified directories to exclude
-    for dir in args.exclude_dirs {
-        excluded_dirs.insert(dir);
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        if written > 0 {
+            self.last_byte = Some(buf[written - 1]);
+        }
+        Ok(written)
     }
 
-    let walker = WalkBuilder::new(&directory)
-        .types(types_matcher)
-        .git_ignore(true)
-        .hidden(true)
-        .filter_entry(move |entry| {
-            let path = entry.path();
-            if let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) {
-                if path.is_dir() && excluded_dirs.contains(dir_name) {
-                    return false;
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the buffer in `format` to `file`, streamed when `stream` is given,
+/// and returns its size. A streamed buffer is wrapped in `tag` here, as
+/// `render` does for the others.
+fn write_buffer_to(
+    file: fs::File,
+    format: OutputFormat,
+    render: &dyn Fn(OutputFormat) -> Result<String, Box<dyn std::error::Error>>,
+    stream: Option<&WriteBuffer>,
+    tag: Option<&str>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut out = CountingWriter {
+        inner: io::BufWriter::new(file),
+        written: 0,
+        last_byte: None,
+    };
+    match stream {
+        Some(write_buffer) => {
+            if let Some(tag) = tag {
+                writeln!(out, "<{}>", tag)?;
+            }
+            write_buffer(format, &mut out)?;
+            if let Some(tag) = tag {
+                if out.last_byte != Some(b'\n') {
+                    writeln!(out)?;
                 }
+                writeln!(out, "</{}>", tag)?;
             }
-            true
+        }
+        None => out.write_all(render(format)?.as_bytes())?,
+    }
+    out.flush()?;
+    Ok(out.written)
+}
+
+/// Writes the buffer to `--output` and copies it to the clipboard, as asked,
+/// then reports where it went. `render` produces the buffer in a given format.
+fn deliver(
+    args: &Cli,
+    render: &dyn Fn(OutputFormat) -> Result<String, Box<dyn std::error::Error>>,
+    stream: Option<&WriteBuffer>,
+    tree: &str,
+    timings: &mut Timings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_format = args
+        .file_format
+        .or(args.format)
+        .or_else(|| {
+            args.output
+                .as_deref()
+                .or(args.append_to.as_deref())
+                .and_then(format_for_path)
         })
-        .build();
+        .unwrap_or(OutputFormat::Comments);
+    let clipboard_format = args
+        .clipboard_format
+        .or(args.format)
+        .unwrap_or(OutputFormat::Comments);
 
-    for result in walker {
-        let entry = result?;
-        let path = entry.path();
+    // The file and clipboard buffers can differ in format, so each is checked.
+    let mut warned = false;
+    let mut check_size = |size: usize| {
+        if let Some(limit) = args.warn_bytes.filter(|&limit| size as u64 > limit) {
+            if !std::mem::replace(&mut warned, true) {
+                anstream::eprintln!(
+                    "{}warning:{} the code buffer ({}) is over --warn-bytes {}; \
+                     -e, --max-files, --max-total-lines or --max-bytes-per-ext can trim it",
+                    log::WARNING.render(),
+                    log::WARNING.render_reset(),
+                    format_size(size, args.raw_bytes),
+                    format_size(limit as usize, args.raw_bytes)
+                );
+            }
+        }
+    };
 
-        if path.is_file() {
-            let extension = path
-                .extension()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_lowercase();
+    let mut destinations = Vec::new();
+    // The size of the buffer --size-history records: the clipboard's, or else the first written.
+    let mut buffer_size = None;
+    if let Some(output) = &args.output {
+        let size = write_buffer_to(
+            fs::File::create(output)?,
+            file_format,
+            render,
+            stream,
+            args.wrap_in_tag.as_deref(),
+        )?;
+        check_size(size);
+        buffer_size.get_or_insert(size);
+        destinations.push(format!(
+            "written to {} ({})",
+            output,
+            format_size(size, args.raw_bytes)
+        ));
+        timings.lap("write output");
+    }
 
-            if !extensions.is_empty() && !extensions.iter().any(|e| e == &extension) {
-                continue;
+    if let Some(path) = &args.append_to {
+        let source = args
+            .archive
+            .as_deref()
+            .or(args.directory.as_deref())
+            .unwrap_or(".");
+        let banner = if args.deterministic {
+            format!("// ===== code_collector: {} =====\n\n", source)
+        } else {
+            format!(
+                "// ===== code_collector: {} ({}) =====\n\n",
+                source,
+                humantime::format_rfc3339_seconds(SystemTime::now())
+            )
+        };
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        file.write_all(banner.as_bytes())?;
+        let size = write_buffer_to(
+            file.try_clone()?,
+            file_format,
+            render,
+            stream,
+            args.wrap_in_tag.as_deref(),
+        )?;
+        check_size(size);
+        buffer_size.get_or_insert(size);
+        let total = file.metadata()?.len();
+        destinations.push(format!(
+            "appended to {} (now {})",
+            path,
+            format_size(total as usize, args.raw_bytes)
+        ));
+        timings.lap("append output");
+    }
+
+    if args.stdout {
+        // The whole buffer is rendered first, so a failure can't leave half of it printed.
+        let code_buffer = render(file_format)?;
+        check_size(code_buffer.len());
+        buffer_size.get_or_insert(code_buffer.len());
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(code_buffer.as_bytes())?;
+        stdout.flush()?;
+        destinations.push(format!(
+            "printed to stdout ({})",
+            format_size(code_buffer.len(), args.raw_bytes)
+        ));
+        timings.lap("stdout");
+    }
+
+    if !args.no_clipboard {
+        let code_buffer = render(clipboard_format)?;
+        check_size(code_buffer.len());
+        buffer_size = Some(code_buffer.len());
+        let size = format_size(code_buffer.len(), args.raw_bytes);
+        match copy_to_clipboard(args, code_buffer, tree) {
+            Ok(1) => destinations.insert(0, format!("copied to the clipboard ({})", size)),
+            Ok(parts) => destinations.insert(
+                0,
+                format!("copied to the clipboard in {} parts ({})", parts, size),
+            ),
+            Err(e) if args.exit_zero_on_clipboard_error => {
+                eprintln!("Could not copy to the clipboard: {}", e);
             }
+            Err(e) => return Err(e),
+        }
+        timings.lap("clipboard");
+    }
+
+    log::flush_summary();
+    if !destinations.is_empty() {
+        info!("Code buffer has been {}.", destinations.join(" and "));
+    }
+    if let (Some(path), Some(size)) = (&args.size_history, buffer_size) {
+        // Absolute, so runs from elsewhere compare with the same entries.
+        let source = args
+            .archive
+            .as_deref()
+            .or(args.directory.as_deref())
+            .unwrap_or(".");
+        let source = fs::canonicalize(source).map_or_else(
+            |_| source.to_string(),
+            |source| source.to_string_lossy().into_owned(),
+        );
+        match update_size_history(Path::new(path), &source, size)? {
+            Some((bytes, tokens)) => info!(
+                "{} / {} tokens since the last run.",
+                format_delta(bytes, size as u64, |n| format_size(
+                    n as usize,
+                    args.raw_bytes
+                )),
+                format_delta(tokens, estimate_tokens_from_bytes(size as u64), |n| {
+                    n.to_string()
+                })
+            ),
+            None => info!(
+                "Recorded the buffer size in {} for the next run to compare.",
+                path
+            ),
+        }
+    }
+    if args.timing {
+        timings.report();
+    }
+    Ok(())
+}
 
-            let comment_syntax = get_comment_syntax(&extension);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            let relative_path = path.strip_prefix(&directory)?;
-            let full_relative_path = relative_path.to_string_lossy();
+    /// A file under the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
 
-            match fs::read_to_string(path) {
-                Ok(content) => {
-                    let mut file_content = String::new();
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!(
+                "code_collector-test-{}-{}",
+                process::id(),
+                name
+            )))
+        }
+    }
 
-                    match comment_syntax {
-                        CommentStyle::Line(prefix) => {
-                            file_content.push_str(&format!("{} {}\n", prefix, full_relative_path));
-                        }
-                        CommentStyle::Block(start, end) => {
-                            file_content
-                                .push_str(&format!("{} {}\n{}\n", start, full_relative_path, end));
-                        }
-                    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
 
-                    file_content.push_str(&content);
-                    file_content.push_str("\n\n");
+    /// A directory under the temporary directory, removed with its contents
+    /// when dropped.
+    struct TempDir(PathBuf);
 
-                    code_buffer.push_str(&file_content);
-                    copied_files.push(relative_path.to_owned());
-                }
-                Err(e) if e.kind() == ErrorKind::InvalidData => {
-                    eprintln!("Skipping binary file {:?}", path);
-                }
-                Err(e) => {
-                    eprintln!("Could not read file {:?}: {}", path, e);
-                }
+    impl TempDir {
+        /// Creates the directory with `files`, given as `(path, content)`.
+        fn with_files(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = TempDir(std::env::temp_dir().join(format!(
+                "code_collector-test-{}-{}",
+                process::id(),
+                name
+            )));
+            let _ = fs::remove_dir_all(&dir.0);
+            for (path, content) in files {
+                let path = dir.0.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
             }
+            dir
+        }
+
+        fn path(&self, relative: &str) -> String {
+            self.0.join(relative).to_string_lossy().into_owned()
         }
     }
 
-    let mut root = TreeNode::new(String::new());
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
 
-    for path in &copied_files {
-        let components: Vec<String> = path
-            .components()
-            .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+    /// The buffer a run over `target` with `args` writes to `--output`, with
+    /// `/` in headers on every platform.
+    fn collect(target: &str, args: &[&str]) -> String {
+        log::set_verbosity(Verbosity::Quiet);
+        let output = TempFile::new(&format!(
+            "collect-{}",
+            target.replace(['/', '\\', ':'], "_")
+        ));
+        let output_path = output.0.to_string_lossy().into_owned();
+        let mut argv = vec![
+            "code_collector",
+            target,
+            "--no-clipboard",
+            "--path-style",
+            "posix",
+        ];
+        argv.extend(["--output", &output_path]);
+        argv.extend(args);
+        run(&Cli::parse_from(argv)).unwrap();
+        fs::read_to_string(&output.0).unwrap()
+    }
+
+    /// A collected file at `path` (relative to the collected directory).
+    fn file(path: &str, content: &str) -> CollectedFile {
+        let extension = file_extension(Path::new(path));
+        CollectedFile {
+            relative_path: PathBuf::from(path),
+            display_path: path.to_string(),
+            language: extension.clone(),
+            extension,
+            content: content.to_string(),
+            link_target: None,
+            modified: None,
+            unchanged: false,
+            notes: Vec::new(),
+            sha256: None,
+            todos: Vec::new(),
+        }
+    }
+
+    /// The default layout: plain headers, no footers or banners.
+    fn format_options() -> FormatOptions {
+        FormatOptions {
+            group_banners: false,
+            footers: false,
+            flatten_max_lines: None,
+            header_space: true,
+            dir_banners: false,
+            header_templates: HashMap::new(),
+            tests_from: None,
+        }
+    }
+
+    fn wrapped(tag: &str, text: &str) -> String {
+        let mut buffer = text.to_string();
+        wrap_in_tag(tag, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn wrap_in_tag_brackets_the_buffer() {
+        assert_eq!(
+            wrapped("codebase", "// a.rs\nfn main() {}\n"),
+            "<codebase>\n// a.rs\nfn main() {}\n</codebase>\n"
+        );
+    }
+
+    #[test]
+    fn wrap_in_tag_of_an_empty_buffer() {
+        assert_eq!(wrapped("codebase", ""), "<codebase>\n</codebase>\n");
+    }
+
+    #[test]
+    fn wrap_in_tag_ends_an_unfinished_last_line() {
+        assert_eq!(wrapped("code", "a\nb"), "<code>\na\nb\n</code>\n");
+    }
+
+    #[test]
+    fn streamed_buffer_is_wrapped_as_a_rendered_one() {
+        let render = |_: OutputFormat| -> Result<String, Box<dyn std::error::Error>> {
+            unreachable!("a streamed buffer isn't rendered")
+        };
+        for text in ["a\nb\n", "a\nb", ""] {
+            let write: &WriteBuffer = &|_, out| Ok(out.write_all(text.as_bytes())?);
+            let file = TempFile::new("streamed-wrap");
+            let size = write_buffer_to(
+                fs::File::create(&file.0).unwrap(),
+                OutputFormat::Comments,
+                &render,
+                Some(write),
+                Some("code"),
+            )
+            .unwrap();
+            let written = fs::read_to_string(&file.0).unwrap();
+            assert_eq!(written, wrapped("code", text));
+            assert_eq!(size, written.len());
+        }
+    }
+
+    #[test]
+    fn markdown_groups_files_by_language() {
+        let files = [
+            file("src/lib.rs", "pub mod a;\n"),
+            file("src/a.rs", "fn a() {}\n"),
+            file("tools/gen.py", "print(1)\n"),
+            file("tests/a.rs", "#[test]\nfn t() {}\n"),
+            file("tests/conftest.py", "import pytest\n"),
+        ];
+        let markdown = format_markdown(&files, "tree\n", Some(3), true);
+        let headings: Vec<&str> = markdown
+            .lines()
+            .filter(|line| line.starts_with('#') && !line.starts_with("#["))
             .collect();
+        assert_eq!(
+            headings,
+            [
+                "# Codebase",
+                "## Rust",
+                "### src/lib.rs",
+                "### src/a.rs",
+                "## Python",
+                "### tools/gen.py",
+                "# Tests",
+                "## Rust",
+                "### tests/a.rs",
+                "## Python",
+                "### tests/conftest.py",
+            ]
+        );
+        assert!(markdown.contains("\n### src/a.rs\n\n```rs\nfn a() {}\n```\n"));
+    }
 
-        root.add_path(&components);
+    #[test]
+    fn summary_blocks_follow_the_format() {
+        let blocks = [
+            SummaryBlock::Lines(Some("Contents:"), "src/  1 file (rs 1)\n".to_string()),
+            SummaryBlock::File(
+                "AI rules: .cursorrules".to_string(),
+                "Be brief.".to_string(),
+            ),
+        ];
+        assert_eq!(
+            render_summary(&blocks, OutputFormat::Comments),
+            "// Contents:\n// src/  1 file (rs 1)\n\n// AI rules: .cursorrules\nBe brief.\n\n"
+        );
+        assert_eq!(
+            render_summary(&blocks, OutputFormat::Markdown),
+            "```\nContents:\nsrc/  1 file (rs 1)\n```\n\n## AI rules: .cursorrules\n\n```\nBe brief.\n```\n\n"
+        );
+        assert_eq!(
+            render_summary(&blocks, OutputFormat::Repomix),
+            "Contents:\nsrc/  1 file (rs 1)\n\nAI rules: .cursorrules\nBe brief.\n\n"
+        );
+    }
+
+    #[test]
+    fn output_extension_picks_the_format() {
+        for (path, format) in [
+            ("context.md", Some(OutputFormat::Markdown)),
+            ("context.markdown", Some(OutputFormat::Markdown)),
+            ("context.xml", Some(OutputFormat::Xml)),
+            ("context.json", Some(OutputFormat::Json)),
+            ("context.txt", None),
+        ] {
+            assert!(format_for_path(path) == format, "{}", path);
+        }
     }
 
-    println!("Copied Files Tree:");
-    root.print("", true);
+    #[test]
+    fn xml_and_json_formats_hold_the_title_and_files() {
+        let dir = TempDir::with_files("xml-json", &[("a.rs", "fn a() {}\n")]);
+        let xml = collect(&dir.path(""), &["--format", "xml", "--title", "A & B"]);
+        assert!(xml.starts_with("<codebase>\n<title>A &amp; B</title>\n<tree>\n"));
+        assert!(xml.contains(
+            "<file path=\"a.rs\" bytes=\"10\" lines=\"1\">\n<![CDATA[fn a() {}]]>\n</file>\n"
+        ));
+        let json = collect(&dir.path(""), &["--format", "json", "--title", "A & B"]);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["title"], "A & B");
+        assert_eq!(json["files"][0]["path"], "a.rs");
+        assert_eq!(json["files"][0]["content"], "fn a() {}\n");
+    }
 
-    // Copy the collected code buffer to the OS clipboard using arboard
-    let mut clipboard = Clipboard::new()?;
-    clipboard.set_text(code_buffer)?;
+    #[test]
+    fn snapshot_hashes_files_as_read() {
+        let dir = TempDir::with_files("snapshot", &[("a.rs", "fn a() {}\nfn b() {}\n")]);
+        let snapshot = TempFile::new("snapshot.sha256");
+        let snapshot_path = snapshot.0.to_string_lossy().into_owned();
+        collect(&dir.path(""), &["--snapshot", &snapshot_path]);
+        let first = fs::read_to_string(&snapshot.0).unwrap();
+        collect(
+            &dir.path(""),
+            &["--snapshot", &snapshot_path, "--head", "1"],
+        );
+        assert_eq!(fs::read_to_string(&snapshot.0).unwrap(), first);
+        assert_eq!(
+            first,
+            format!("{}  a.rs\n", sha256_hex(b"fn a() {}\nfn b() {}\n"))
+        );
+    }
 
-    println!("Code buffer has been copied to the clipboard.");
+    #[test]
+    fn single_file_argument() {
+        let dir = TempDir::with_files(
+            "single-file",
+            &[
+                ("src/main.rs", "fn main() {}\n"),
+                ("src/lib.rs", "pub fn f() {}\n"),
+            ],
+        );
+        assert_eq!(
+            collect(&dir.path("src/main.rs"), &[]),
+            "// main.rs\nfn main() {}\n\n\n"
+        );
+    }
 
-    Ok(())
+    #[test]
+    fn strip_bom_removes_one_leading_mark() {
+        assert_eq!(
+            strip_bom("\u{FEFF}fn main() {}\n".to_string()),
+            "fn main() {}\n"
+        );
+        assert_eq!(strip_bom("\u{FEFF}\u{FEFF}x".to_string()), "\u{FEFF}x");
+        assert_eq!(strip_bom("x\u{FEFF}".to_string()), "x\u{FEFF}");
+    }
+
+    #[test]
+    fn normalize_path_keeps_ordinary_paths() {
+        for path in ["src/main.rs", "/home/me/project", "../up"] {
+            assert_eq!(normalize_path(Path::new(path)), Path::new(path));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn normalize_path_strips_verbatim_prefixes() {
+        assert_eq!(
+            normalize_path(Path::new(r"\\?\C:\project\src")),
+            Path::new(r"C:\project\src")
+        );
+        let long = format!(r"\\?\C:\{}", "d\\".repeat(200));
+        assert_eq!(normalize_path(Path::new(&long)), Path::new(&long));
+    }
+
+    #[test]
+    fn dockerignore_excludes_paths() {
+        let dir = TempDir::with_files(
+            "dockerignore",
+            &[
+                (".dockerignore", "docs/\n"),
+                ("docs/guide.md", "# Guide\n"),
+                ("src/main.rs", "fn main() {}\n"),
+            ],
+        );
+        let root = dir.path("");
+        assert!(collect(&root, &[]).contains("guide.md"));
+        let buffer = collect(&root, &["--use-dockerignore"]);
+        assert!(buffer.contains("// src/main.rs\n"));
+        assert!(!buffer.contains("guide.md"));
+    }
+
+    #[test]
+    fn limit_line_length_truncates() {
+        assert_eq!(
+            limit_line_length("fit\nabcdefghij\nabcd", 4, LineLengthMode::Truncate),
+            "fit\nabcd…\nabcd"
+        );
+    }
+
+    #[test]
+    fn limit_line_length_wraps() {
+        assert_eq!(
+            limit_line_length("abcdefghij\nok\n", 4, LineLengthMode::Wrap),
+            "abcd\nefgh\nij\nok\n"
+        );
+        assert_eq!(
+            limit_line_length("ééééé", 2, LineLengthMode::Wrap),
+            "éé\néé\né"
+        );
+    }
+
+    #[test]
+    fn footers_use_the_header_comment_style() {
+        let options = FormatOptions {
+            footers: true,
+            ..format_options()
+        };
+        assert_eq!(
+            file("src/main.rs", "fn main() {}\n").format(&options),
+            "// src/main.rs\nfn main() {}\n// end src/main.rs\n\n\n"
+        );
+        assert_eq!(
+            file("setup.py", "import os").format(&options),
+            "# setup.py\nimport os\n# end setup.py\n\n\n"
+        );
+        assert_eq!(
+            file("index.html", "<p></p>\n").format(&options),
+            "<!-- index.html\n-->\n<p></p>\n<!-- end index.html -->\n\n\n"
+        );
+        assert_eq!(
+            file("style.css", "p {}\n").format(&options),
+            "/* style.css\n*/\np {}\n/* end style.css */\n\n\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_applies_time_filters_and_limits() {
+        let dir = TempDir::with_files(
+            "dry-run-filters",
+            &[("old.rs", ""), ("a.rs", ""), ("b.rs", "")],
+        );
+        let old = fs::File::options()
+            .write(true)
+            .open(dir.path("old.rs"))
+            .unwrap();
+        old.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_577_836_800))
+            .unwrap();
+        let target = dir.path("");
+        let listed = |args: &[&str]| {
+            log::set_verbosity(Verbosity::Quiet);
+            let mut argv = vec!["code_collector", target.as_str()];
+            argv.extend(args);
+            run(&Cli::parse_from(argv)).unwrap()
+        };
+        assert_eq!(listed(&["--dry-run", "--newer-than", "2021-01-01"]), 2);
+        assert_eq!(listed(&["--dry-run", "--modified-within", "1d"]), 2);
+        assert_eq!(listed(&["--dry-run", "--json", "--max-files", "1"]), 1);
+        assert_eq!(
+            listed(&["--dry-run", "--stats-json", "--max-files", "1"]),
+            1
+        );
+        assert_eq!(listed(&["--print0", "--max-files", "1"]), 1);
+        assert_eq!(listed(&["--print0", "--newer-than", "2021-01-01"]), 2);
+    }
+
+    #[test]
+    fn dry_run_json_entry_shape() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            dry_run_entry(Path::new("src/main.rs"), 120, Some(modified), "rs").to_string(),
+            r#"{"path":"src/main.rs","bytes":120,"mtime":"2023-11-14T22:13:20Z","extension":"rs","would_include":true}"#
+        );
+        assert_eq!(
+            dry_run_entry(Path::new("Makefile"), 0, None, "")["mtime"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn normalize_indent_mixed_tabs_and_spaces() {
+        let mixed = "fn f() {\n\tlet a = 1;\n    let b\t= 2;\n  \tif a {\n\t    b\n\t}\n}";
+        assert_eq!(
+            normalize_indent(mixed, IndentMode::Spaces(4)),
+            "fn f() {\n    let a = 1;\n    let b\t= 2;\n    if a {\n        b\n    }\n}"
+        );
+        assert_eq!(
+            normalize_indent(mixed, IndentMode::Tabs(4)),
+            "fn f() {\n\tlet a = 1;\n\tlet b\t= 2;\n\tif a {\n\t\tb\n\t}\n}"
+        );
+        assert_eq!(
+            normalize_indent("      x\n", IndentMode::Tabs(4)),
+            "\t  x\n"
+        );
+    }
+
+    #[test]
+    fn header_space_toggle() {
+        let without = FormatOptions {
+            header_space: false,
+            ..format_options()
+        };
+        let rust = file("src/main.rs", "fn main() {}\n");
+        assert_eq!(
+            rust.format(&format_options()),
+            "// src/main.rs\nfn main() {}\n\n\n"
+        );
+        assert_eq!(rust.format(&without), "//src/main.rs\nfn main() {}\n\n\n");
+        assert_eq!(
+            file("style.css", "p {}\n").format(&without),
+            "/*style.css\n*/\np {}\n\n\n"
+        );
+    }
+
+    #[test]
+    fn exclude_dir_glob_matches_the_full_path() {
+        let dir = TempDir::with_files(
+            "exclude-dir-glob",
+            &[
+                ("frontend/gen/a.js", "let a;\n"),
+                ("backend/gen/b.js", "let b;\n"),
+                ("frontend/app.js", "let app;\n"),
+            ],
+        );
+        let buffer = collect(&dir.path(""), &["--exclude-dir-glob", "frontend/gen"]);
+        assert!(!buffer.contains("a.js"));
+        assert!(buffer.contains("// backend/gen/b.js\n"));
+        assert!(buffer.contains("// frontend/app.js\n"));
+    }
+
+    #[test]
+    fn deterministic_runs_are_identical() {
+        let files = [
+            ("project/src/main.rs", "fn main() {}\r\n"),
+            ("project/src/b.rs", "pub fn b() {}\n"),
+            ("project/README.md", "# Project\n"),
+        ];
+        let here = TempDir::with_files("deterministic-here", &files);
+        let there = TempDir::with_files("deterministic-there", &files);
+        let args = [
+            "--deterministic",
+            "--with-summary-header",
+            "--format",
+            "markdown",
+        ];
+        let first = collect(&here.path("project"), &args);
+        assert_eq!(collect(&here.path("project"), &args), first);
+        assert_eq!(collect(&there.path("project"), &args), first);
+        assert!(!first.contains('\r'));
+        assert!(!first.contains("Date:"));
+        assert!(first.contains("Collected from: project\n"));
+        assert!(first.contains("    project/\n"));
+    }
+
+    #[test]
+    fn parent_gitignore_applies_to_a_nested_directory() {
+        let dir = TempDir::with_files(
+            "parent-ignores",
+            &[
+                (".git/HEAD", "ref: refs/heads/main\n"),
+                (".gitignore", "*.log\n/app/secret.txt\n"),
+                ("app/main.py", "print(1)\n"),
+                ("app/debug.log", "trace\n"),
+                ("app/secret.txt", "hunter2\n"),
+            ],
+        );
+        let buffer = collect(&dir.path("app"), &[]);
+        assert!(buffer.contains("# main.py\n"));
+        assert!(!buffer.contains("debug.log"));
+        assert!(!buffer.contains("secret.txt"));
+        let buffer = collect(&dir.path("app"), &["--no-parent-ignores"]);
+        assert!(buffer.contains("debug.log"));
+        assert!(buffer.contains("secret.txt"));
+    }
 }
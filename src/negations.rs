@@ -0,0 +1,118 @@
+//! Ignore-file negations that reach into the built-in directory excludes.
+//!
+//! `node_modules/` and the like are pruned by name before the walker's ignore
+//! rules are consulted, so a `!node_modules/my-lib/` in a `.gitignore` would
+//! never be seen. These matchers let such a negation open the directory up
+//! just far enough for the paths it names. Only the `.ignore` and
+//! `.gitignore` files between the collected directory and the excluded one
+//! are read; a negation is taken to reach into a directory when its pattern
+//! starts with that directory's path.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+struct IgnoreFile {
+    matcher: Gitignore,
+    /// The `!` patterns, without the `!`.
+    negations: Vec<String>,
+}
+
+pub struct Negations {
+    root: PathBuf,
+    case_insensitive: bool,
+    loaded: Mutex<HashMap<PathBuf, Arc<Vec<IgnoreFile>>>>,
+}
+
+impl Negations {
+    pub fn new(root: &Path, case_insensitive: bool) -> Self {
+        Negations {
+            root: root.to_owned(),
+            case_insensitive,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a negation names a path below `dir`, so it has to be walked.
+    pub fn reaches_into(&self, dir: &Path) -> bool {
+        self.ancestors(dir).any(|(ancestor, files)| {
+            let mut relative = format!(
+                "{}/",
+                dir.strip_prefix(&ancestor).unwrap_or(dir).to_string_lossy()
+            );
+            relative = relative.replace('\\', "/");
+            if self.case_insensitive {
+                relative = relative.to_lowercase();
+            }
+            files
+                .iter()
+                .flat_map(|file| &file.negations)
+                .any(|pattern| {
+                    let pattern = pattern.trim_start_matches('/').trim_start_matches("**/");
+                    if self.case_insensitive {
+                        pattern.to_lowercase().starts_with(&relative)
+                    } else {
+                        pattern.starts_with(&relative)
+                    }
+                })
+        })
+    }
+
+    /// Whether the nearest rule matching `path` or one of its parents is a
+    /// negation.
+    pub fn is_whitelisted(&self, path: &Path, is_dir: bool) -> bool {
+        for (_, files) in self.ancestors(path) {
+            for file in files.iter() {
+                let matched = file.matcher.matched_path_or_any_parents(path, is_dir);
+                if matched.is_whitelist() {
+                    return true;
+                }
+                if matched.is_ignore() {
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    /// The directories above `path` up to the root, nearest first, with
+    /// their ignore files (`.ignore` before `.gitignore`, as it wins).
+    fn ancestors<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> impl Iterator<Item = (PathBuf, Arc<Vec<IgnoreFile>>)> + 'a {
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .map(|dir| (dir.to_owned(), self.ignore_files(dir)))
+    }
+
+    fn ignore_files(&self, dir: &Path) -> Arc<Vec<IgnoreFile>> {
+        let mut loaded = self.loaded.lock().unwrap();
+        let files = loaded.entry(dir.to_owned()).or_insert_with(|| {
+            let files = [".ignore", ".gitignore"]
+                .iter()
+                .filter_map(|name| {
+                    let text = fs::read_to_string(dir.join(name)).ok()?;
+                    let mut builder = GitignoreBuilder::new(dir);
+                    builder.case_insensitive(self.case_insensitive).ok()?;
+                    let mut negations = Vec::new();
+                    for line in text.lines() {
+                        builder.add_line(None, line).ok()?;
+                        if let Some(pattern) = line.trim_end().strip_prefix('!') {
+                            negations.push(pattern.to_string());
+                        }
+                    }
+                    Some(IgnoreFile {
+                        matcher: builder.build().ok()?,
+                        negations,
+                    })
+                })
+                .collect();
+            Arc::new(files)
+        });
+        Arc::clone(files)
+    }
+}
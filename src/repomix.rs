@@ -0,0 +1,78 @@
+//! Output in the shape of repomix's `plain` style (repomix 0.2.x), for tooling
+//! that parses that layout: a file summary, the directory structure, then each
+//! file between `================` separators under a `File: <path>` line.
+
+use crate::CollectedFile;
+
+const SECTION_RULE: &str = "================================================================";
+const FILE_RULE: &str = "================";
+
+const SUMMARY: &str = "\
+Purpose:
+--------
+This file contains a packed representation of the entire repository's contents.
+It is designed to be easily consumable by AI systems for analysis, code review,
+or other automated processes.
+
+File Format:
+------------
+The content is organized as follows:
+1. This summary section
+2. Directory structure
+3. Multiple file entries, each consisting of:
+  a. A separator line (================)
+  b. The file path (File: path/to/file)
+  c. Another separator line
+  d. The full contents of the file
+  e. A blank line
+
+Usage Guidelines:
+-----------------
+- This file should be treated as read-only. Any changes should be made to the
+  original repository files, not this packed version.
+- When processing this file, use the file path to distinguish
+  between different files in the repository.
+- Be aware that this file may contain sensitive information. Handle it with
+  the same level of security as you would the original repository.
+
+Notes:
+------
+- Some files may have been excluded based on .gitignore rules and code_collector's configuration
+- Binary files are not included in this packed representation
+";
+
+fn section(out: &mut String, title: &str) {
+    out.push_str(&format!("{}\n{}\n{}\n", SECTION_RULE, title, SECTION_RULE));
+}
+
+/// Formats `files`, with `tree` as the directory structure section.
+pub fn format_repomix(files: &[CollectedFile], tree: &str) -> String {
+    let mut out = String::from(
+        "This file is a merged representation of the entire codebase, combining all repository files into a single document.\n\
+         Generated by code_collector.\n\n",
+    );
+
+    section(&mut out, "File Summary");
+    out.push('\n');
+    out.push_str(SUMMARY);
+    out.push('\n');
+
+    section(&mut out, "Directory Structure");
+    out.push_str(tree);
+    out.push('\n');
+
+    section(&mut out, "Files");
+    out.push('\n');
+    for file in files {
+        out.push_str(&format!(
+            "{}\nFile: {}\n{}\n",
+            FILE_RULE, file.display_path, FILE_RULE
+        ));
+        out.push_str(&file.content);
+        if !file.content.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
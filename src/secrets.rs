@@ -0,0 +1,57 @@
+//! Patterns for credentials that shouldn't end up in a shared buffer.
+//!
+//! The set favors well-known token formats with distinctive prefixes, which
+//! rarely match by accident; the generic `key = "..."` assignment pattern is
+//! the main source of false positives (e.g. test fixtures or placeholders).
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+const PATTERNS: &[(&str, &str)] = &[
+    ("AWS access key ID", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "private key",
+        r"-----BEGIN (?:RSA |EC |DSA |OPENSSH |PGP |ENCRYPTED )?PRIVATE KEY( BLOCK)?-----",
+    ),
+    (
+        "GitHub token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})\b",
+    ),
+    ("GitLab token", r"\bglpat-[A-Za-z0-9_-]{20,}\b"),
+    ("Slack token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}\b"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}\b"),
+    ("Stripe secret key", r"\b(?:sk|rk)_live_[0-9A-Za-z]{20,}\b"),
+    (
+        "secret assignment",
+        r#"(?i)\b(?:api[_-]?key|secret[_-]?key|access[_-]?token|auth[_-]?token|password|passwd|client[_-]?secret)\b\s*[:=]\s*["'][^"'\s]{8,}["']"#,
+    ),
+];
+
+/// One suspected secret in a file.
+pub struct SecretMatch {
+    /// 1-based line number.
+    pub line: usize,
+    /// Which kind of secret the line looks like, e.g. `AWS access key ID`.
+    pub kind: &'static str,
+}
+
+fn patterns() -> &'static [(&'static str, Regex)] {
+    static COMPILED: OnceLock<Vec<(&'static str, Regex)>> = OnceLock::new();
+    COMPILED.get_or_init(|| {
+        PATTERNS
+            .iter()
+            .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("valid secret pattern")))
+            .collect()
+    })
+}
+
+/// Finds the lines of `content` that look like they contain a secret.
+pub fn find_secrets(content: &str) -> Vec<SecretMatch> {
+    let mut matches = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if let Some((kind, _)) = patterns().iter().find(|(_, regex)| regex.is_match(line)) {
+            matches.push(SecretMatch { line: i + 1, kind });
+        }
+    }
+    matches
+}
@@ -0,0 +1,34 @@
+//! Cheap parse checks for `--validate-syntax`, for the languages that have a
+//! parser at hand.
+
+use serde::Deserialize;
+
+/// Parses `content` as the language of `extension`.
+///
+/// Returns `None` if there is no checker for the extension, otherwise whether
+/// the parse succeeded, with the parser's message on failure.
+pub fn check(extension: &str, content: &str) -> Option<Result<(), String>> {
+    let result = match extension {
+        "json" => serde_json::from_str::<serde_json::Value>(content)
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        "toml" => content
+            .parse::<toml::Table>()
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        "yaml" | "yml" => check_yaml(content),
+        "rs" => syn::parse_file(content)
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// YAML files may hold several `---`-separated documents; each must parse.
+fn check_yaml(content: &str) -> Result<(), String> {
+    for document in serde_yaml::Deserializer::from_str(content) {
+        serde_yaml::Value::deserialize(document).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
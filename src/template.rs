@@ -0,0 +1,167 @@
+use crate::CollectedFile;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use tera::{Context, Tera, Value};
+
+#[derive(Serialize)]
+struct TemplateFile<'a> {
+    path: &'a str,
+    ext: &'a str,
+    lang: &'a str,
+    content: &'a str,
+    bytes: usize,
+    lines: usize,
+    sha256: Option<&'a str>,
+}
+
+/// A template that failed to load, parse or render.
+///
+/// Tera nests the useful detail (including the line and column of syntax
+/// errors) in the error's source chain, so the whole chain is kept.
+pub struct TemplateError {
+    path: String,
+    message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "template {}: {}", self.path, self.message)
+    }
+}
+
+impl fmt::Debug for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for TemplateError {}
+
+fn describe(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push('\n');
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// The `cdata` filter: the text as an XML CDATA section, which holds anything
+/// but `]]>`. A `]]>` in the text is split across two sections.
+fn cdata(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("the cdata filter only takes a string"))?;
+    Ok(Value::String(format!(
+        "<![CDATA[{}]]>",
+        text.replace("]]>", "]]]]><![CDATA[>")
+    )))
+}
+
+/// The `--format xml` layout, also shipped as an example template.
+const XML_TEMPLATE: &str = include_str!("../templates/xml.tera");
+
+/// Renders the collected files through the Tera template at `path`.
+///
+/// The template sees `files` (each with `path`, `ext`, `lang`, `content`,
+/// `bytes`, `lines` and, with `--with-hash`, `sha256`), `tree`, the
+/// rendered file tree, and `title`. `lang` is the extension after
+/// `--lang-map`. Besides Tera's own filters (`escape_xml` among them) there
+/// is `cdata`.
+pub fn render_template(
+    path: &str,
+    files: &[CollectedFile],
+    tree: &str,
+    title: Option<&str>,
+) -> Result<String, TemplateError> {
+    let source = fs::read_to_string(path).map_err(|e| TemplateError {
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+    render(path, &source, files, tree, title, None)
+}
+
+/// Renders the collected files in the `--format xml` layout, with `summary`,
+/// the summary blocks as plain text, ahead of the tree.
+pub fn render_xml(
+    files: &[CollectedFile],
+    tree: &str,
+    title: Option<&str>,
+    summary: &str,
+) -> Result<String, TemplateError> {
+    render("xml.tera", XML_TEMPLATE, files, tree, title, Some(summary))
+}
+
+/// Renders the template `source`, named `name` in errors.
+fn render(
+    name: &str,
+    source: &str,
+    files: &[CollectedFile],
+    tree: &str,
+    title: Option<&str>,
+    summary: Option<&str>,
+) -> Result<String, TemplateError> {
+    let to_error = |message: String| TemplateError {
+        path: name.to_string(),
+        message,
+    };
+
+    let mut tera = Tera::default();
+    tera.register_filter("cdata", cdata);
+    tera.add_raw_template(name, source)
+        .map_err(|e| to_error(describe(&e)))?;
+
+    let template_files: Vec<TemplateFile> = files
+        .iter()
+        .map(|file| TemplateFile {
+            path: &file.display_path,
+            ext: &file.extension,
+            lang: &file.language,
+            content: &file.content,
+            bytes: file.content.len(),
+            lines: file.content.lines().count(),
+            sha256: file.sha256.as_deref(),
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("files", &template_files);
+    context.insert("tree", tree);
+    context.insert("title", &title);
+    if let Some(summary) = summary {
+        context.insert("summary", summary);
+    }
+
+    tera.render(name, &context)
+        .map_err(|e| to_error(describe(&e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cdata_of(text: &str) -> String {
+        match cdata(&Value::String(text.to_string()), &HashMap::new()).unwrap() {
+            Value::String(section) => section,
+            other => panic!("not a string: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cdata_wraps_text() {
+        assert_eq!(cdata_of("a < b && c"), "<![CDATA[a < b && c]]>");
+    }
+
+    #[test]
+    fn cdata_splits_its_terminator() {
+        assert_eq!(
+            cdata_of("x[a[1]]>0</file>"),
+            "<![CDATA[x[a[1]]]]><![CDATA[>0</file>]]>"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Token counts for `--tokenizer`: exact ones from a model's BPE tokenizer
+//! when built with the `tiktoken` feature, otherwise the four characters per
+//! token of [`estimate_tokens`].
+
+use crate::estimate_tokens;
+use crate::log::notice;
+
+#[cfg(feature = "tiktoken")]
+static BPE: std::sync::OnceLock<&'static tiktoken_rs::CoreBPE> = std::sync::OnceLock::new();
+
+/// Counts tokens with `model`'s tokenizer from now on, or says why they're
+/// still estimated.
+pub fn set_model(model: &str) {
+    #[cfg(feature = "tiktoken")]
+    match tiktoken_rs::bpe_for_model(model) {
+        Ok(bpe) => {
+            BPE.get_or_init(|| bpe);
+        }
+        Err(_) => notice!(
+            "No tokenizer is known for --tokenizer {}, so tokens are estimated at four characters each.",
+            model
+        ),
+    }
+    #[cfg(not(feature = "tiktoken"))]
+    notice!(
+        "Built without the tiktoken feature, so --tokenizer {} is ignored and tokens are estimated at four characters each.",
+        model
+    );
+}
+
+/// Whether [`count_tokens`] is exact rather than estimated.
+pub fn is_exact() -> bool {
+    #[cfg(feature = "tiktoken")]
+    return BPE.get().is_some();
+    #[cfg(not(feature = "tiktoken"))]
+    false
+}
+
+/// The tokens in `text`, by the `--tokenizer` model if there is one.
+pub fn count_tokens(text: &str) -> usize {
+    #[cfg(feature = "tiktoken")]
+    if let Some(bpe) = BPE.get() {
+        return bpe.count_ordinary(text);
+    }
+    estimate_tokens(text)
+}
+
+/// `n` tokens for a message, marked `~` when they're estimated.
+pub fn describe_tokens(n: usize) -> String {
+    format!("{}{} tokens", if is_exact() { "" } else { "~" }, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "tiktoken"))]
+    #[test]
+    fn estimates_without_a_model() {
+        assert_eq!(count_tokens("fn main() {}"), 3);
+        assert_eq!(describe_tokens(3), "~3 tokens");
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn counts_exactly_with_a_model() {
+        set_model("gpt-4o");
+        assert!(is_exact());
+        assert_eq!(count_tokens("hello world"), 2);
+        assert_eq!(describe_tokens(2), "2 tokens");
+    }
+}
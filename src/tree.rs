@@ -0,0 +1,535 @@
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+const DIR_STYLE: Style = AnsiColor::Blue.on_default().bold();
+const UNCOLLECTED_STYLE: Style = Style::new().dimmed();
+
+/// The characters used to connect nodes to their parents.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum TreeStyle {
+    /// Box-drawing characters (`├──`, `└──`)
+    #[default]
+    Unicode,
+    /// Plain ASCII connectors (`|--`, `` `-- ``)
+    Ascii,
+    /// Indentation with tabs only
+    Tabs,
+}
+
+/// Why a path is in the tree without having been collected.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Uncollected {
+    /// Listed by `--seed-tree-from`, shown as `(not collected)`.
+    Listed,
+    /// Skipped as binary, shown as `(binary)` with `--show-binaries-in-tree`.
+    Binary,
+}
+
+/// How sibling names are ordered.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum TreeSort {
+    /// Byte order, so `Zoo.rs` comes before `apple.rs`
+    #[default]
+    Case,
+    /// Ignoring case, as most editors do; names differing only in case keep byte order
+    Nocase,
+}
+
+impl TreeSort {
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            TreeSort::Case => a.cmp(b),
+            TreeSort::Nocase => a
+                .to_lowercase()
+                .cmp(&b.to_lowercase())
+                .then_with(|| a.cmp(b)),
+        }
+    }
+}
+
+struct Connectors {
+    branch: &'static str,
+    last_branch: &'static str,
+    /// Continues an ancestor that has more siblings below.
+    through: &'static str,
+    /// Indents under an ancestor that was the last of its siblings.
+    blank: &'static str,
+    /// Ends a name cut short by `max_width`.
+    ellipsis: &'static str,
+}
+
+impl TreeStyle {
+    fn connectors(self) -> Connectors {
+        match self {
+            TreeStyle::Unicode => Connectors {
+                branch: "├── ",
+                last_branch: "└── ",
+                through: "│   ",
+                blank: "    ",
+                ellipsis: "…",
+            },
+            TreeStyle::Ascii => Connectors {
+                branch: "|-- ",
+                last_branch: "`-- ",
+                through: "|   ",
+                blank: "    ",
+                ellipsis: "...",
+            },
+            TreeStyle::Tabs => Connectors {
+                branch: "",
+                last_branch: "",
+                through: "\t",
+                blank: "\t",
+                ellipsis: "…",
+            },
+        }
+    }
+}
+
+/// Controls how [`TreeNode::render`] draws the tree.
+#[derive(Default)]
+pub struct TreeOptions {
+    /// Annotate directories with the number of files below them.
+    pub file_counts: bool,
+    /// Characters connecting each node to its parent.
+    pub style: TreeStyle,
+    /// Order of siblings.
+    pub sort: TreeSort,
+    /// Stop after this many files, ending with a `... and N more files` line.
+    pub max_entries: Option<usize>,
+    /// Show directories at this depth (1 for the top level) as `(+N files)`
+    /// instead of listing their contents.
+    pub max_depth: Option<usize>,
+    /// Color directories and uncollected paths, for printing to a terminal.
+    pub colored: bool,
+    /// Cut names short, ending them with an ellipsis, so that no line is
+    /// wider than this many columns (a tab taking eight). Notes such as
+    /// `(binary)` are kept whole.
+    pub max_width: Option<usize>,
+}
+
+pub struct TreeNode {
+    name: String,
+    children: HashMap<String, TreeNode>,
+    /// Whether a collected file ends at this node (as opposed to a directory).
+    is_file: bool,
+    /// A path listed only for the tree's sake, e.g. a binary that isn't collected.
+    uncollected: Option<Uncollected>,
+}
+
+impl TreeNode {
+    pub fn new(name: String) -> Self {
+        TreeNode {
+            name,
+            children: HashMap::new(),
+            is_file: false,
+            uncollected: None,
+        }
+    }
+
+    pub fn add_path(&mut self, path_components: &[String]) {
+        let node = self.node_at(path_components);
+        node.is_file = true;
+        node.uncollected = None;
+    }
+
+    /// Adds a directory, which is shown even if nothing was collected in it.
+    pub fn add_directory(&mut self, path_components: &[String]) {
+        self.node_at(path_components);
+    }
+
+    /// Adds a path that is shown in the tree, marked `(not collected)` or
+    /// `(binary)`, unless it was collected after all (or is a directory
+    /// holding collected files).
+    pub fn add_uncollected_path(&mut self, path_components: &[String], why: Uncollected) {
+        let node = self.node_at(path_components);
+        if !node.is_file && node.children.is_empty() {
+            node.uncollected = Some(why);
+        }
+    }
+
+    fn node_at(&mut self, path_components: &[String]) -> &mut TreeNode {
+        let Some(name) = path_components.first() else {
+            return self;
+        };
+        self.children
+            .entry(name.clone())
+            .or_insert_with(|| TreeNode::new(name.clone()))
+            .node_at(&path_components[1..])
+    }
+
+    /// Merges each directory whose only child is another directory into one
+    /// node named `a/b/c`, like the compact folders of some file explorers.
+    pub fn collapse_single_child_dirs(&mut self) {
+        for (_, mut child) in std::mem::take(&mut self.children) {
+            child.collapse_single_child_dirs();
+            if child.is_dir() && child.children.len() == 1 {
+                let only_child = child.children.values().next().unwrap();
+                if only_child.is_dir() {
+                    let (_, grandchild) = child.children.drain().next().unwrap();
+                    child.name = format!("{}/{}", child.name, grandchild.name);
+                    child.children = grandchild.children;
+                }
+            }
+            self.children.insert(child.name.clone(), child);
+        }
+    }
+
+    /// The directories that directly hold collected files, depth first in
+    /// `options.sort` order, each with the path components leading to it and
+    /// a tree of its own entries, subdirectories shown as `name/` leaves.
+    pub fn directory_fragments(&self, options: &TreeOptions) -> Vec<(Vec<String>, String)> {
+        let mut fragments = Vec::new();
+        self.collect_fragments(&mut Vec::new(), options, &mut fragments);
+        fragments
+    }
+
+    fn collect_fragments(
+        &self,
+        path: &mut Vec<String>,
+        options: &TreeOptions,
+        fragments: &mut Vec<(Vec<String>, String)>,
+    ) {
+        if self.children.values().any(|child| child.is_file) {
+            let name = match (path.is_empty(), self.name.is_empty()) {
+                (false, _) => format!("{}/", path.join("/")),
+                (true, false) => self.name.clone(),
+                (true, true) => "./".to_string(),
+            };
+            let mut fragment = TreeNode::new(name);
+            for child in self.children.values() {
+                let mut leaf = TreeNode::new(child.name.clone());
+                if child.is_dir() {
+                    leaf.name.push('/');
+                } else {
+                    leaf.is_file = child.is_file;
+                    leaf.uncollected = child.uncollected;
+                }
+                fragment.children.insert(leaf.name.clone(), leaf);
+            }
+            fragments.push((path.clone(), fragment.render(options)));
+        }
+        let mut keys: Vec<&String> = self.children.keys().collect();
+        keys.sort_by(|a, b| options.sort.compare(a, b));
+        for key in keys {
+            let child = &self.children[key];
+            if child.is_dir() {
+                // A name merged by `collapse_single_child_dirs` holds several components.
+                let depth = path.len();
+                path.extend(child.name.split('/').map(String::from));
+                child.collect_fragments(path, options, fragments);
+                path.truncate(depth);
+            }
+        }
+    }
+
+    /// The tree as nested JSON objects for other tools to draw: each has a
+    /// `name` and a `type` (`file` or `directory`); directories also have
+    /// `files`, the number of files below them, and their `children` in
+    /// `sort` order. Paths added with [`Self::add_uncollected_path`] are
+    /// marked `"collected": false`, and skipped binaries `"binary": true` too.
+    pub fn to_json(&self, sort: TreeSort) -> serde_json::Value {
+        if self.is_file || self.uncollected.is_some() {
+            let mut node = serde_json::json!({"name": self.name, "type": "file"});
+            if let Some(why) = self.uncollected {
+                node["collected"] = false.into();
+                if why == Uncollected::Binary {
+                    node["binary"] = true.into();
+                }
+            }
+            return node;
+        }
+        let mut keys: Vec<&String> = self.children.keys().collect();
+        keys.sort_by(|a, b| sort.compare(a, b));
+        let children: Vec<serde_json::Value> = keys
+            .iter()
+            .map(|key| self.children[*key].to_json(sort))
+            .collect();
+        serde_json::json!({
+            "name": self.name,
+            "type": "directory",
+            "files": self.file_count(),
+            "children": children,
+        })
+    }
+
+    fn is_dir(&self) -> bool {
+        !self.is_file && !self.children.is_empty()
+    }
+
+    /// Counts the files at or below this node.
+    fn file_count(&self) -> usize {
+        usize::from(self.is_file)
+            + self
+                .children
+                .values()
+                .map(TreeNode::file_count)
+                .sum::<usize>()
+    }
+
+    /// Counts the nodes without children: files, and paths listed as not collected.
+    fn leaf_count(&self) -> usize {
+        if self.children.is_empty() {
+            return 1;
+        }
+        self.children.values().map(TreeNode::leaf_count).sum()
+    }
+
+    /// Renders the tree one node per line, connected to its parents as
+    /// `options.style` draws them.
+    ///
+    /// A root with a name (e.g. the collected directory) gets a line of its
+    /// own above its children; an unnamed root is left out.
+    pub fn render(&self, options: &TreeOptions) -> String {
+        let (out, hidden) = self.render_top(options, false);
+        if hidden == 0 {
+            return out;
+        }
+        // The `... and N more files` line ends the top level, so it is drawn
+        // again without a last entry above that line.
+        let (mut out, hidden) = self.render_top(options, true);
+        let connectors = options.style.connectors();
+        out.push_str(&format!(
+            "{}{}... and {} more files\n",
+            connectors.blank, connectors.last_branch, hidden
+        ));
+        out
+    }
+
+    /// The tree, and how many files `max_entries` left out of it.
+    fn render_top(&self, options: &TreeOptions, truncated: bool) -> (String, usize) {
+        let mut render = Render {
+            out: String::new(),
+            options,
+            connectors: options.style.connectors(),
+            shown: 0,
+            truncated,
+        };
+        let blank = render.connectors.blank;
+        if !self.name.is_empty() {
+            let label = self.label(options, "", blank, render.connectors.ellipsis);
+            render.out.push_str(&format!("{}{}\n", blank, label));
+        }
+        self.render_children(&mut render, blank, 1);
+        let hidden = if self.children.is_empty() || options.max_entries.is_none() {
+            0
+        } else {
+            self.leaf_count() - render.shown.min(self.leaf_count())
+        };
+        (render.out, hidden)
+    }
+
+    /// The node's name and notes, then `extra`, cut to fit after `prefix`.
+    fn label(&self, options: &TreeOptions, extra: &str, prefix: &str, ellipsis: &str) -> String {
+        let style = if !options.colored {
+            Style::new()
+        } else if self.is_dir() {
+            DIR_STYLE
+        } else if self.uncollected.is_some() {
+            UNCOLLECTED_STYLE
+        } else {
+            Style::new()
+        };
+        let mut notes = String::new();
+        if options.file_counts && !self.children.is_empty() {
+            let count = self.file_count();
+            notes.push_str(&format!(
+                " ({} {})",
+                count,
+                if count == 1 { "file" } else { "files" }
+            ));
+        }
+        match self.uncollected {
+            Some(Uncollected::Listed) => notes.push_str(" (not collected)"),
+            Some(Uncollected::Binary) => notes.push_str(" (binary)"),
+            None => {}
+        }
+        notes.push_str(extra);
+        let name = match options.max_width {
+            Some(width) => {
+                let room = width.saturating_sub(columns(prefix) + columns(&notes));
+                shorten(&self.name, room, ellipsis)
+            }
+            None => self.name.clone(),
+        };
+        format!(
+            "{}{}{}{}",
+            style.render(),
+            name,
+            style.render_reset(),
+            notes
+        )
+    }
+
+    fn render_into(&self, render: &mut Render, prefix: &str, is_last: bool, depth: usize) {
+        let collapsed = !self.children.is_empty() && render.options.max_depth == Some(depth);
+        let mut extra = String::new();
+        if self.children.is_empty() {
+            render.shown += 1;
+        } else if collapsed {
+            let hidden = self.leaf_count();
+            render.shown += hidden;
+            extra = format!(
+                " (+{} {})",
+                hidden,
+                if hidden == 1 { "file" } else { "files" }
+            );
+        }
+        let connectors = &render.connectors;
+        let branch = if is_last {
+            connectors.last_branch
+        } else {
+            connectors.branch
+        };
+        let label = self.label(
+            render.options,
+            &extra,
+            &format!("{}{}", prefix, branch),
+            connectors.ellipsis,
+        );
+        let child_prefix = format!(
+            "{}{}",
+            prefix,
+            if is_last {
+                connectors.blank
+            } else {
+                connectors.through
+            }
+        );
+        render
+            .out
+            .push_str(&format!("{}{}{}\n", prefix, branch, label));
+        if !collapsed {
+            self.render_children(render, &child_prefix, depth + 1);
+        }
+    }
+
+    fn render_children(&self, render: &mut Render, prefix: &str, depth: usize) {
+        let mut keys: Vec<&String> = self.children.keys().collect();
+        keys.sort_by(|a, b| render.options.sort.compare(a, b));
+        for (i, key) in keys.iter().enumerate() {
+            if render
+                .options
+                .max_entries
+                .is_some_and(|max| render.shown >= max)
+            {
+                break;
+            }
+            let child = self.children.get(*key).unwrap();
+            let is_last = i == keys.len() - 1 && !(depth == 1 && render.truncated);
+            child.render_into(render, prefix, is_last, depth);
+        }
+    }
+}
+
+/// The width of `text` in a terminal, counting a tab as eight columns.
+fn columns(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { 8 } else { 1 }).sum()
+}
+
+/// `name`, or as much of it as fits in `room` columns with `ellipsis` after.
+/// At least one character is kept, even if that runs over.
+fn shorten(name: &str, room: usize, ellipsis: &str) -> String {
+    if columns(name) <= room {
+        return name.to_string();
+    }
+    let kept = room.saturating_sub(columns(ellipsis)).max(1);
+    format!(
+        "{}{}",
+        name.chars().take(kept).collect::<String>(),
+        ellipsis
+    )
+}
+
+/// State shared across one [`TreeNode::render`] call.
+struct Render<'a> {
+    out: String,
+    options: &'a TreeOptions,
+    connectors: Connectors,
+    /// Files drawn so far, counting those summarized under a collapsed directory.
+    shown: usize,
+    /// Whether a `... and N more files` line follows the top level.
+    truncated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(paths: &[&str]) -> TreeNode {
+        let mut root = TreeNode::new(String::new());
+        for path in paths {
+            let components: Vec<String> = path.split('/').map(String::from).collect();
+            root.add_path(&components);
+        }
+        root
+    }
+
+    fn render(style: TreeStyle) -> String {
+        let options = TreeOptions {
+            style,
+            ..TreeOptions::default()
+        };
+        tree(&["src/main.rs", "src/lib.rs", "README.md"]).render(&options)
+    }
+
+    #[test]
+    fn unicode_style() {
+        assert_eq!(
+            render(TreeStyle::Unicode),
+            "    ├── README.md\n    └── src\n        ├── lib.rs\n        └── main.rs\n"
+        );
+    }
+
+    #[test]
+    fn ascii_style() {
+        assert_eq!(
+            render(TreeStyle::Ascii),
+            "    |-- README.md\n    `-- src\n        |-- lib.rs\n        `-- main.rs\n"
+        );
+    }
+
+    #[test]
+    fn truncated_tree_ends_with_the_overflow_line() {
+        let options = TreeOptions {
+            max_entries: Some(2),
+            ..TreeOptions::default()
+        };
+        assert_eq!(
+            tree(&["a.rs", "b.rs", "c.rs", "d/e.rs"]).render(&options),
+            "    ├── a.rs\n    ├── b.rs\n    └── ... and 2 more files\n"
+        );
+        assert_eq!(
+            tree(&["a.rs", "d/e.rs", "d/f.rs", "d/g.rs"]).render(&options),
+            "    ├── a.rs\n    ├── d\n    │   ├── e.rs\n    └── ... and 2 more files\n"
+        );
+    }
+
+    #[test]
+    fn mixed_case_names_sort() {
+        let mut names = vec!["Zoo.rs", "apple.rs", "banana.rs", "Apple.rs"];
+        names.sort_by(|a, b| TreeSort::Case.compare(a, b));
+        assert_eq!(names, ["Apple.rs", "Zoo.rs", "apple.rs", "banana.rs"]);
+        names.sort_by(|a, b| TreeSort::Nocase.compare(a, b));
+        assert_eq!(names, ["Apple.rs", "apple.rs", "banana.rs", "Zoo.rs"]);
+
+        let options = TreeOptions {
+            sort: TreeSort::Nocase,
+            ..TreeOptions::default()
+        };
+        assert_eq!(
+            tree(&["Zoo.rs", "apple.rs", "Lib/b.rs"]).render(&options),
+            "    ├── apple.rs\n    ├── Lib\n    │   └── b.rs\n    └── Zoo.rs\n"
+        );
+    }
+
+    #[test]
+    fn tabs_style() {
+        assert_eq!(
+            render(TreeStyle::Tabs),
+            "\tREADME.md\n\tsrc\n\t\tlib.rs\n\t\tmain.rs\n"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Splitting a collected buffer back into its files, for `--unpack`.
+//!
+//! Only the comments format is read. A line counts as a file header when it
+//! is the comment a file of that path would be headed with (`// src/main.rs`,
+//! `# setup.py`, or `<!-- index.html` over a `-->` line) and it opens the
+//! buffer or follows the blank lines that end the previous file. That is a
+//! guess: a file holding a comment that looks just like a header, after two
+//! blank lines, is split in two. Flattened files, `--merge-adjacent-blank-headers`
+//! banners and header templates aren't recognized, and neither are paths with
+//! a space in them: the path ends at the first space, as otherwise any comment
+//! after two blank lines would pass for a header.
+
+use crate::{file_extension, get_comment_syntax, CommentStyle};
+use std::path::{Path, PathBuf};
+
+/// One file of the buffer.
+pub struct Section {
+    /// As written in the header, relative to the collected directory.
+    pub path: PathBuf,
+    pub content: String,
+    /// Headed `[unchanged]` by `--diff-against`, so its content isn't there.
+    pub unchanged: bool,
+}
+
+/// The files of `buffer`, in order. Anything before the first header (a
+/// title, the summary blocks) is left out.
+pub fn sections(buffer: &str) -> Vec<Section> {
+    let lines: Vec<&str> = buffer.split_inclusive('\n').collect();
+    let is_blank = |i: usize| lines[i].trim_end_matches(['\n', '\r']).is_empty();
+    let mut sections: Vec<Section> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        // Files end with two blank lines, and the header of an unchanged
+        // file with one; before the first file, any blank line (after a
+        // title, say) will do.
+        let separated = match (i, sections.last()) {
+            (0, _) => true,
+            (_, None) => is_blank(i - 1),
+            (_, Some(last)) if last.unchanged => is_blank(i - 1),
+            (1, Some(_)) => false,
+            (_, Some(_)) => is_blank(i - 1) && is_blank(i - 2),
+        };
+        let header = if separated {
+            header(lines[i], lines.get(i + 1).copied())
+        } else {
+            None
+        };
+        if let Some((path, unchanged, header_lines)) = header {
+            if let Some(last) = sections.last_mut() {
+                finish(last);
+            }
+            sections.push(Section {
+                path,
+                content: String::new(),
+                unchanged,
+            });
+            i += header_lines;
+            continue;
+        }
+        if let Some(section) = sections.last_mut() {
+            section.content.push_str(lines[i]);
+        }
+        i += 1;
+    }
+    if let Some(last) = sections.last_mut() {
+        finish(last);
+    }
+    sections
+}
+
+/// Drops the separator after a section's content, and its `end <path>`
+/// footer if it has one.
+fn finish(section: &mut Section) {
+    let content = &mut section.content;
+    if content.ends_with("\n\n") {
+        content.truncate(content.len() - 2);
+    }
+    let path = section.path.to_string_lossy().replace('\\', "/");
+    let footer = |space: &str| match comment_style(&section.path) {
+        CommentStyle::Line(prefix) => format!("{}{}end {}\n", prefix, space, path),
+        CommentStyle::Block(start, end) => {
+            format!("{}{}end {}{}{}\n", start, space, path, space, end)
+        }
+    };
+    for footer in [footer(" "), footer("")] {
+        if content.ends_with(&footer) {
+            content.truncate(content.len() - footer.len());
+            break;
+        }
+    }
+    if section.unchanged {
+        content.clear();
+    }
+}
+
+fn comment_style(path: &Path) -> CommentStyle {
+    get_comment_syntax(&file_extension(path))
+}
+
+/// The path in a header line, whether the file is marked `[unchanged]`, and
+/// how many lines the header takes.
+fn header(line: &str, next: Option<&str>) -> Option<(PathBuf, bool, usize)> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    for (start, end) in [
+        ("//", None),
+        ("#", None),
+        ("<!--", Some("-->")),
+        ("/*", Some("*/")),
+    ] {
+        let Some(text) = line.strip_prefix(start) else {
+            continue;
+        };
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        // The path, then any of ` -> target`, ` [unchanged]`, ` [sha256:..]`.
+        let (path, rest) = text.split_once(' ').unwrap_or((text, ""));
+        let rest = rest.trim();
+        if path.is_empty() || !(rest.is_empty() || rest.starts_with("->") || rest.starts_with('['))
+        {
+            continue;
+        }
+        let path = PathBuf::from(path);
+        let matches = match (comment_style(&path), end) {
+            (CommentStyle::Line(prefix), None) => prefix == start,
+            (CommentStyle::Block(open, close), Some(end)) => {
+                open == start && close == end && next.is_some_and(|next| next.trim() == end)
+            }
+            _ => false,
+        };
+        if matches {
+            let unchanged = rest.contains("[unchanged]");
+            return Some((path, unchanged, if end.is_some() { 2 } else { 1 }));
+        }
+    }
+    None
+}